@@ -1,13 +1,9 @@
 extern crate xml_tree;
 
-use std::fs::File;
-
-use xml_tree::{build, EventReader};
+use xml_tree::Document;
 
 fn main() {
-    let file = File::open("data/ex1.xml").unwrap();
-    let mut parser = EventReader::new(file);
-    let res = build(&mut parser);
+    let res = Document::from_path("data/ex1.xml", None);
     match res {
         Err(ref err) => println!("{}", err),
         Ok(ref doc) => {
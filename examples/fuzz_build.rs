@@ -0,0 +1,21 @@
+//! afl harness for the builder. This is a plain binary which afl runs with
+//! each input on stdin, it is not a cargo-fuzz target.
+//!
+//! Reads a document from stdin and builds it, errors are expected, panics are bugs.
+//!
+//!     cargo build --example fuzz_build
+//!     afl-fuzz -i data -o findings target/debug/examples/fuzz_build
+
+extern crate xml_tree;
+
+use std::io::{self, Read};
+
+use xml_tree::Document;
+
+fn main() {
+    let mut input = Vec::new();
+    io::stdin().read_to_end(&mut input).unwrap();
+    if let Ok(doc) = Document::from_reader(&input[..], None) {
+        doc.to_string();
+    }
+}
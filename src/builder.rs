@@ -1,12 +1,29 @@
-use std::io::Read;
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::mem;
+use std::path::Path;
+use std::rc::Rc;
+use std::usize;
 
-use dom::{Document, Element, Text, Node, rc_node_new, RcElement, rc_element_new, rc_text_new};
-use error::BuildError;
+use dom::{escape_attribute, Comment, Doctype, Document, Element, Position, ProcessingInstruction, Text, Node, RcNode, RcElement};
+use dom::{rc_comment_new, rc_element_new, rc_node_new, rc_processing_instruction_new, rc_text_new};
+use error::{BuildError, PartialBuildError};
+use options::{BuildOptions, WhitespaceMode};
 
-use xml::common::XmlVersion;
+use xml::attribute::OwnedAttribute;
+use xml::common::{Error, HasPosition, XmlVersion};
+use xml::name::OwnedName;
+use xml::namespace::{NS_XMLNS_PREFIX, NS_XML_PREFIX, NS_XML_URI};
 use xml::reader::EventReader;
+use xml::reader::config::ParserConfig;
 use xml::reader::events::XmlEvent;
 
+// names starting with xml are reserved, but the parser accepts them, so
+// a fragment could still close the wrapper, which is rejected
+const FRAGMENT_WRAPPER: &'static str = "xml-tree-fragment";
+
 /// `build` creates a `Document` from an `EventReader`.
 ///
 /// `build` iterates through the events yielded by the provided `EventReader`.
@@ -14,118 +31,1690 @@ use xml::reader::events::XmlEvent;
 /// a reference counted DOM that you can then manipulate. The builder will return
 /// a `BuildError` if the source document is invalid of if it is empty.
 ///
-/// The builder should not panic. It should only panic in case of an impossible
-/// scenario. Please report any panics.
+/// The builder does not panic, whatever the sequence of events it is given.
+///
+/// The parser reports an XML declaration even if the source document has
+/// none, the document then has one too, and it does not report the document
+/// type declaration at all. `build_from_reader` watches the bytes of the
+/// source document to get both right.
 pub fn build<B: Read>(reader: &mut EventReader<B>) -> Result<Document, BuildError> {
-    let mut root: Option<RcElement> = None;
-    let mut curr: Option<RcElement> = None;
-    let mut doc_version: Option<XmlVersion> = None;
-    let mut doc_encoding: Option<String> = None;
+    build_with_options(reader, &BuildOptions::new())
+}
+
+/// `build_with_options` creates a `Document` from an `EventReader`,
+/// like `build`, according to the given `BuildOptions`.
+pub fn build_with_options<B: Read>(reader: &mut EventReader<B>, options: &BuildOptions) -> Result<Document, BuildError> {
+    let mut builder = TreeBuilder::with_options(options.clone());
+    loop {
+        // the parser reports its position after an event, so the one
+        // after the previous event is where this one starts
+        let pos = Position::new(reader.row() + 1, reader.col() + 1);
+        let event = reader.next();
+        let end = match event {
+            XmlEvent::EndDocument | XmlEvent::Error(_) => true,
+            _ => false,
+        };
+        try!(builder.feed_with_position(event, pos));
+        if end {
+            break;
+        }
+    }
+    builder.finish()
+}
+
+/// `build_partial` creates a `Document` from an `EventReader`, like
+/// `build_with_options`. On failure, the error carries what was built
+/// until then, to tell how far the source document was processed.
+pub fn build_partial<B: Read>(reader: &mut EventReader<B>, options: &BuildOptions) -> Result<Document, PartialBuildError> {
+    let mut builder = TreeBuilder::with_options(options.clone());
+    loop {
+        let pos = Position::new(reader.row() + 1, reader.col() + 1);
+        let event = reader.next();
+        let end = match event {
+            XmlEvent::EndDocument | XmlEvent::Error(_) => true,
+            _ => false,
+        };
+        if let Err(err) = builder.feed_with_position(event, pos) {
+            return Err(PartialBuildError {
+                error: err,
+                document: builder.partial(),
+                open: builder.path(),
+            });
+        }
+        if end {
+            break;
+        }
+    }
+    let document = builder.partial();
+    let open = builder.path();
+    builder.finish().map_err(|err| {
+        PartialBuildError {
+            error: err,
+            document: document,
+            open: open,
+        }
+    })
+}
+
+/// `build_lenient` creates a `Document` from an `EventReader`, recovering
+/// from errors in the source document. It returns the document along with
+/// the errors recovered from, and fails only if there is no root element.
+/// See `TreeBuilder::new_lenient`.
+///
+/// The parser stops at its first error, so the document ends there, with
+/// its open elements closed. This salvages the beginning of truncated or
+/// corrupted documents, and that is all the recovery there is from bytes:
+/// the parser itself rejects stray and mismatched end tags, and elements
+/// after the root element. The builder only recovers from those when it
+/// is fed such events directly, see `TreeBuilder::new_lenient`.
+pub fn build_lenient<B: Read>(reader: &mut EventReader<B>, options: &BuildOptions) -> Result<(Document, Vec<BuildError>), BuildError> {
+    let mut builder = TreeBuilder::new_lenient(options.clone());
+    loop {
+        let pos = Position::new(reader.row() + 1, reader.col() + 1);
+        let event = reader.next();
+        let end = match event {
+            XmlEvent::EndDocument | XmlEvent::Error(_) => true,
+            _ => false,
+        };
+        try!(builder.feed_with_position(event, pos));
+        if end {
+            break;
+        }
+    }
+    builder.finish_lenient()
+}
+
+/// `build_from_events` creates a `Document` from any sequence of events,
+/// such as events which were synthesized, filtered or recorded.
+/// See `TreeBuilder`.
+pub fn build_from_events<I: IntoIterator<Item=XmlEvent>>(events: I) -> Result<Document, BuildError> {
+    let mut builder = TreeBuilder::new();
+    for event in events {
+        try!(builder.feed(event));
+    }
+    builder.finish()
+}
+
+/// `TreeBuilder` creates a `Document` from events fed to it one at a time.
+///
+/// Events after `EndDocument` are ignored. The end of the document is also
+/// reached by calling `finish`, there is no need to feed an `EndDocument`.
+/// Once an event is rejected, the builder should not be fed any further.
+pub struct TreeBuilder {
+    options: BuildOptions,
+    root: Option<RcElement>,
+    // the open elements, from the root down
+    stack: Vec<RcElement>,
+    // whether whitespace is preserved by xml:space, for each open element
+    space: Vec<bool>,
+    version: Option<XmlVersion>,
+    encoding: Option<String>,
+    standalone: Option<bool>,
+    prolog: Vec<RcNode>,
+    epilog: Vec<RcNode>,
+    ended: bool,
+    // top level elements and text are allowed, in the prolog
+    fragment: bool,
+    // counted against the limits of the options
+    nodes: usize,
+    // recover from errors, recording them
+    lenient: bool,
+    diagnostics: Vec<BuildError>,
+    // depth of the element being skipped, if any
+    skip: usize,
+}
+
+impl TreeBuilder {
+
+    /// Create a builder with the default `BuildOptions`.
+    pub fn new() -> TreeBuilder {
+        TreeBuilder::with_options(BuildOptions::new())
+    }
+
+    pub fn with_options(options: BuildOptions) -> TreeBuilder {
+        TreeBuilder {
+            options: options,
+            root: None,
+            stack: Vec::new(),
+            space: Vec::new(),
+            version: None,
+            encoding: None,
+            standalone: None,
+            prolog: Vec::new(),
+            epilog: Vec::new(),
+            ended: false,
+            fragment: false,
+            nodes: 0,
+            lenient: false,
+            diagnostics: Vec::new(),
+            skip: 0,
+        }
+    }
+
+    /// Create a builder for a fragment, which can have any number of top
+    /// level elements and text, to be ended with `finish_fragment`.
+    pub fn new_fragment(options: BuildOptions) -> TreeBuilder {
+        let mut builder = TreeBuilder::with_options(options);
+        builder.fragment = true;
+        builder
+    }
+
+    /// Create a builder which recovers from errors in the source document
+    /// instead of rejecting it, to be ended with `finish_lenient`.
+    ///
+    /// Stray end tags are ignored, elements left open are closed by the end
+    /// tag of an ancestor or by the end of the document, and elements after
+    /// the root element are skipped, as is text outside of the root element.
+    /// Parser errors and exceeded limits end the document. The parser reports
+    /// the errors above as parser errors, so this recovery only applies to
+    /// events which do not come straight from it.
+    pub fn new_lenient(options: BuildOptions) -> TreeBuilder {
+        let mut builder = TreeBuilder::with_options(options);
+        builder.lenient = true;
+        builder
+    }
+
+    /// Add an event to the document.
+    pub fn feed(&mut self, event: XmlEvent) -> Result<(), BuildError> {
+        self.feed_at(event, None)
+    }
+
+    /// Add an event to the document, along with the position at which
+    /// it was read in the source document.
+    pub fn feed_with_position(&mut self, event: XmlEvent, pos: Position) -> Result<(), BuildError> {
+        self.feed_at(event, Some(pos))
+    }
+
+    /// End the document and get it.
+    pub fn finish(self) -> Result<Document, BuildError> {
+        try!(self.check_closed());
+        match self.root {
+            None => Err(BuildError::UndefinedRoot),
+            Some (root) => {
+                Ok(Document {
+                    version: self.version,
+                    encoding: self.encoding,
+                    standalone: self.standalone,
+                    doctype: None,
+                    doctype_index: 0,
+                    prolog: self.prolog,
+                    epilog: self.epilog,
+                    root: root,
+                })
+            }
+        }
+    }
+
+    /// Get the document built so far, sharing its nodes with the builder.
+    /// Returns `None` until the root element starts.
+    pub fn partial(&self) -> Option<Document> {
+        self.root.as_ref().map(|root| {
+            Document {
+                version: self.version,
+                encoding: self.encoding.clone(),
+                standalone: self.standalone,
+                doctype: None,
+                doctype_index: 0,
+                prolog: self.prolog.clone(),
+                epilog: self.epilog.clone(),
+                root: root.clone(),
+            }
+        })
+    }
+
+    /// End the document and get it, closing the elements left open,
+    /// along with the errors recovered from. Fails only without a root element.
+    pub fn finish_lenient(mut self) -> Result<(Document, Vec<BuildError>), BuildError> {
+        if let Err(err) = self.check_closed() {
+            self.diagnostics.push(err);
+        }
+        self.stack.clear();
+        let diagnostics = mem::replace(&mut self.diagnostics, Vec::new());
+        self.finish().map(|doc| (doc, diagnostics))
+    }
+
+    /// End the fragment and get its top level nodes.
+    pub fn finish_fragment(self) -> Result<Vec<RcNode>, BuildError> {
+        try!(self.check_closed());
+        Ok(self.prolog)
+    }
+
+    fn check_closed(&self) -> Result<(), BuildError> {
+        match self.stack.last() {
+            None => Ok(()),
+            Some(elem) => {
+                Err(BuildError::UnclosedElement {
+                    name: qualified_name(&elem.borrow().name),
+                    path: element_path(elem),
+                })
+            }
+        }
+    }
+
+    fn feed_at(&mut self, event: XmlEvent, pos: Option<Position>) -> Result<(), BuildError> {
+        if self.ended {
+            return Ok(());
+        }
+        if !self.lenient {
+            return self.add_event(event, pos);
+        }
+
+        if self.skip > 0 {
+            match event {
+                XmlEvent::StartElement { .. } => self.skip += 1,
+                XmlEvent::EndElement { .. } => self.skip -= 1,
+                XmlEvent::EndDocument => self.ended = true,
+                _ => {}
+            }
+            return Ok(());
+        }
+        if let XmlEvent::EndElement { ref name } = event {
+            if !self.stack.is_empty() {
+                // close the elements left open within the one which ends
+                let open = self.stack.iter().rposition(|elem| same_name(&elem.borrow().name, name));
+                match open {
+                    None => {
+                        let err = BuildError::UnexpectedEndTag {
+                            name: qualified_name(name),
+                            position: pos,
+                            path: self.path(),
+                        };
+                        self.diagnostics.push(err);
+                        return Ok(());
+                    }
+                    Some(i) if i + 1 < self.stack.len() => {
+                        let err = BuildError::MismatchedTag {
+                            expected: qualified_name(&self.stack[self.stack.len() - 1].borrow().name),
+                            found: qualified_name(name),
+                            position: pos,
+                            path: self.path(),
+                        };
+                        self.diagnostics.push(err);
+                        self.stack.truncate(i + 1);
+                        self.space.truncate(i + 1);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        match self.add_event(event, pos) {
+            Ok(()) => {}
+            Err(err) => {
+                match err {
+                    BuildError::MultipleRoots { .. } => self.skip = 1,
+                    BuildError::TextOutsideRoot { .. } | BuildError::UnexpectedEndTag { .. } => {}
+                    _ => self.ended = true,
+                }
+                self.diagnostics.push(err);
+            }
+        }
+        Ok(())
+    }
 
-    for event in reader.events() {
+    fn add_event(&mut self, event: XmlEvent, pos: Option<Position>) -> Result<(), BuildError> {
+        try!(self.check_limits(&event, pos));
         match event {
             // StartDocument
-            XmlEvent::StartDocument { version, encoding, standalone: _ } => {
-                doc_version = Some(version);
-                doc_encoding = Some(encoding);
+            XmlEvent::StartDocument { version, encoding, standalone } => {
+                self.version = Some(version);
+                self.encoding = Some(encoding);
+                self.standalone = standalone;
             }
             // StartElement
             XmlEvent::StartElement { name, attributes, namespace } => {
-                // Check if a root exists.
-                match root {
-                    // If it does not, it must be created.
-                    // Also sets the current element to the root.
+                let preserve = match xml_space(&attributes) {
+                    Some(preserve) => self.options.respect_xml_space && preserve,
+                    None => *self.space.last().unwrap_or(&false),
+                };
+                let mut elem = match self.stack.last() {
+                    // The first element is the root, a fragment has no root.
+                    None if self.fragment => Element::new_root(name, attributes, namespace),
+                    None if self.root.is_none() => {
+                        Element::new_root(name, attributes, namespace)
+                    }
+                    // There can only be one.
                     None => {
-                        let elem = Element::new_root(name, attributes, namespace);
-                        let rcelem = rc_element_new(elem);
-                        root = Some(rcelem.clone());
-                        curr = Some(rcelem.clone());
-                    }
-                    // If it does, the new element will be appended,
-                    // to the current element.
-                    Some (_) => {
-                        match curr {
-                            // This should never happen.
-                            None => panic!("Root is set but current is not."),
-                            Some(parent) => {
-                                // create the element with the parent as a weak reference
-                                let elem = Element::new(parent.clone().downgrade(), name, attributes, namespace);
-                                let rcelem = rc_element_new(elem);
-                                // create a node from the element
-                                let node = Node::Element(rcelem.clone());
-                                let rcnode = rc_node_new(node);
-                                // add the element to the parent
-                                parent.borrow_mut().add_child(rcnode);
-                                // move into the new element
-                                curr = Some(rcelem.clone());
-                            }
-                        }
+                        return Err(BuildError::MultipleRoots {
+                            name: qualified_name(&name),
+                            position: pos,
+                        });
+                    }
+                    // Otherwise, the new element is a child of the current element,
+                    // which it refers to with a weak reference.
+                    Some(parent) => {
+                        Element::new(parent.clone().downgrade(), name, attributes, namespace)
+                    }
+                };
+                elem.start = pos;
+                try!(self.count_node(pos));
+                let rcelem = rc_element_new(elem);
+                match self.stack.last().cloned() {
+                    None if self.fragment => self.add_top_level(Node::Element(rcelem.clone())),
+                    None => self.root = Some(rcelem.clone()),
+                    Some(parent) => {
+                        let node = Node::Element(rcelem.clone());
+                        parent.borrow_mut().add_child(rc_node_new(node));
                     }
                 }
+                // move into the new element
+                self.stack.push(rcelem);
+                self.space.push(preserve);
             }
             // EndElement
-            XmlEvent::EndElement { name: _ } => {
-                match curr {
-                    // This should never happen.
-                    None => panic!("End element before start element."),
-                    Some(elem) => {
-                        // move out of the element by setting the current
-                        // element to the parent of the element we're
-                        // exiting
-                        match elem.borrow().parent {
-                            // current has no parent, so it's probably root
-                            None => {
-                                // check if root is valid
-                                match root {
-                                    // This should never happen.
-                                    None => panic!("End element without any root."),
-                                    Some(ref root) => {
-                                        curr = Some(root.clone());
-                                    }
-                                }
-                            }
-                            Some(ref parent) => {
-                                curr = Some(parent.clone().upgrade()
-                                    .expect("Unable to upgrade WeakReference, parent was dropped."));
-                            }
-                        }
+            XmlEvent::EndElement { name } => {
+                let elem = match self.stack.last().cloned() {
+                    Some(elem) => elem,
+                    None => {
+                        return Err(BuildError::UnexpectedEndTag {
+                            name: qualified_name(&name),
+                            position: pos,
+                            path: Vec::new(),
+                        });
+                    }
+                };
+                {
+                    let open = &elem.borrow().name;
+                    if !same_name(open, &name) {
+                        return Err(BuildError::MismatchedTag {
+                            expected: qualified_name(open),
+                            found: qualified_name(&name),
+                            position: pos,
+                            path: element_path(&elem),
+                        });
+                    }
+                }
+                self.stack.pop();
+                self.space.pop();
+                elem.borrow_mut().end = pos;
+            },
+            // Cdata
+            XmlEvent::CData(content) => {
+                try!(self.check_text(&content, pos));
+                if self.fragment && self.stack.is_empty() {
+                    let mut text = Text::new_root(content);
+                    text.cdata = true;
+                    text.position = pos;
+                    self.add_top_level(Node::Text(rc_text_new(text)));
+                } else {
+                    try!(add_text(self.stack.last(), content, true, self.options.coalesce_text, pos));
+                }
+            },
+            // Characters or Whitespace
+            XmlEvent::Characters(content) | XmlEvent::Whitespace(content) => {
+                let preserve = *self.space.last().unwrap_or(&false);
+                if let Some(content) = filter_text(&self.options, content, preserve) {
+                    try!(self.check_text(&content, pos));
+                    if self.stack.is_empty() && (self.fragment || content.trim().is_empty()) {
+                        // whitespace outside of the root element, or fragment text
+                        let mut text = Text::new_root(content);
+                        text.position = pos;
+                        self.add_top_level(Node::Text(rc_text_new(text)));
+                    } else {
+                        try!(add_text(self.stack.last(), content, false, self.options.coalesce_text, pos));
                     }
                 }
             },
-            // Cdata or Characters
-            XmlEvent::CData(content) | XmlEvent::Characters(content) => {
-                match curr {
-                    // This should never happen.
-                    None => panic!("Text node before any element."),
-                    Some(ref parent) => {
-                        // create the text node with the parent as a weak reference
-                        let text = Text::new(parent.clone().downgrade(), content);
-                        let rctext = rc_text_new(text);
-                        // create wrapper node
-                        let node = Node::Text(rctext);
-                        let rcnode = rc_node_new(node);
-                        // add the text node to the parent
-                        parent.borrow_mut().add_child(rcnode.clone());
+            // Comment
+            XmlEvent::Comment(content) => {
+                try!(self.count_node(pos));
+                match self.stack.last().cloned() {
+                    // outside of the root element
+                    None => {
+                        let mut comment = Comment::new_root(content);
+                        comment.position = pos;
+                        self.add_top_level(Node::Comment(rc_comment_new(comment)));
+                    }
+                    Some(parent) => {
+                        let mut comment = Comment::new(parent.clone().downgrade(), content);
+                        comment.position = pos;
+                        let node = Node::Comment(rc_comment_new(comment));
+                        parent.borrow_mut().add_child(rc_node_new(node));
+                    }
+                }
+            },
+            // ProcessingInstruction
+            XmlEvent::ProcessingInstruction { name, data } => {
+                try!(self.count_node(pos));
+                match self.stack.last().cloned() {
+                    // outside of the root element
+                    None => {
+                        let mut pi = ProcessingInstruction::new_root(name, data);
+                        pi.position = pos;
+                        self.add_top_level(Node::ProcessingInstruction(rc_processing_instruction_new(pi)));
+                    }
+                    Some(parent) => {
+                        let mut pi = ProcessingInstruction::new(parent.clone().downgrade(), name, data);
+                        pi.position = pos;
+                        let node = Node::ProcessingInstruction(rc_processing_instruction_new(pi));
+                        parent.borrow_mut().add_child(rc_node_new(node));
                     }
                 }
             },
+            // EndDocument
+            XmlEvent::EndDocument => self.ended = true,
             // Error
-            XmlEvent::Error(err) => {
-                return Err(BuildError::ParserError(err));
+            XmlEvent::Error(err) => return Err(BuildError::ParserError(err, self.path())),
+        }
+        Ok(())
+    }
+
+    /// Check the names and attributes of an event against the limits of
+    /// the options, before anything is allocated for it in the document.
+    fn check_limits(&self, event: &XmlEvent, pos: Option<Position>) -> Result<(), BuildError> {
+        match *event {
+            XmlEvent::StartElement { ref name, ref attributes, ref namespace } => {
+                try!(self.check_limit("depth", self.options.max_depth, self.stack.len() + 1, pos));
+                try!(self.check_limit("attributes", self.options.max_attributes, attributes.len(), pos));
+                // every element keeps a copy of the bindings in scope
+                try!(self.check_limit("namespaces", self.options.max_namespaces, namespace.0.len(), pos));
+                try!(self.check_name(name, pos));
+                for attr in attributes.iter() {
+                    try!(self.check_name(&attr.name, pos));
+                    try!(self.check_limit("text length", self.options.max_text_length, attr.value.len(), pos));
+                }
+            }
+            XmlEvent::Comment(ref content) => {
+                try!(self.check_limit("text length", self.options.max_text_length, content.len(), pos));
+            }
+            XmlEvent::ProcessingInstruction { ref name, ref data } => {
+                try!(self.check_limit("name length", self.options.max_name_length, name.len(), pos));
+                let length = data.as_ref().map(|data| data.len()).unwrap_or(0);
+                try!(self.check_limit("text length", self.options.max_text_length, length, pos));
             }
-            // Ignore other events.
             _ => {}
         }
+        Ok(())
     }
 
-    match root {
-        None => Err(BuildError::UndefinedRoot),
-        Some (root) => {
-            Ok(Document {
-                version: doc_version,
-                encoding: doc_encoding,
-                root: root.clone(),
-            })
+    /// Check both parts of a name against `max_name_length`.
+    fn check_name(&self, name: &OwnedName, pos: Option<Position>) -> Result<(), BuildError> {
+        let prefix = name.prefix.as_ref().map(|prefix| prefix.len()).unwrap_or(0);
+        try!(self.check_limit("name length", self.options.max_name_length, prefix, pos));
+        self.check_limit("name length", self.options.max_name_length, name.local_name.len(), pos)
+    }
+
+    /// Count a node about to be appended to the document.
+    fn count_node(&mut self, pos: Option<Position>) -> Result<(), BuildError> {
+        try!(self.check_limit("nodes", self.options.max_nodes, self.nodes + 1, pos));
+        self.nodes += 1;
+        Ok(())
+    }
+
+    /// Check text about to be appended to the current element, once its
+    /// whitespace is handled. It is counted as a node unless it is
+    /// coalesced with the last text node.
+    fn check_text(&mut self, content: &str, pos: Option<Position>) -> Result<(), BuildError> {
+        let mut length = content.len();
+        let mut coalesced = false;
+        if self.options.coalesce_text {
+            if let Some(parent) = self.stack.last() {
+                if let Some(last) = parent.borrow().iter().last() {
+                    if let Node::Text(ref text) = *last.borrow() {
+                        length += text.borrow().content.len();
+                        coalesced = true;
+                    }
+                }
+            }
+        }
+        try!(self.check_limit("text length", self.options.max_text_length, length, pos));
+        if !coalesced {
+            try!(self.count_node(pos));
+        }
+        Ok(())
+    }
+
+    fn check_limit(&self, limit: &'static str, max: Option<usize>, value: usize, pos: Option<Position>) -> Result<(), BuildError> {
+        match max {
+            Some(max) if value > max => {
+                Err(BuildError::LimitExceeded {
+                    limit: limit,
+                    max: max,
+                    position: pos,
+                    path: self.path(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the qualified names of the open elements, from the root down.
+    fn path(&self) -> Vec<String> {
+        match self.stack.last() {
+            None => Vec::new(),
+            Some(elem) => element_path(elem),
+        }
+    }
+
+    /// Append a node found outside of the root element to the prolog,
+    /// or to the epilog if the root element was already seen.
+    fn add_top_level(&mut self, node: Node) {
+        match self.root {
+            None => self.prolog.push(rc_node_new(node)),
+            Some(_) => self.epilog.push(rc_node_new(node)),
+        }
+    }
+
+}
+
+/// Apply the whitespace handling of the options to the content of a text node.
+/// Returns `None` if the text node should be dropped.
+fn filter_text(options: &BuildOptions, content: String, preserve: bool) -> Option<String> {
+    if preserve {
+        return Some(content);
+    }
+    match options.whitespace {
+        WhitespaceMode::Preserve => Some(content),
+        WhitespaceMode::Drop => {
+            if content.trim().is_empty() {
+                None
+            } else {
+                Some(content)
+            }
+        }
+        WhitespaceMode::Trim => {
+            let trimmed = content.trim();
+            if trimmed.is_empty() {
+                None
+            } else {
+                Some(trimmed.to_string())
+            }
+        }
+    }
+}
+
+/// Get the value of the `xml:space` attribute, true meaning "preserve".
+fn xml_space(attributes: &[OwnedAttribute]) -> Option<bool> {
+    for attr in attributes.iter() {
+        let name = attr.name.borrow();
+        if name.local_name == "space" && (name.prefix == Some(NS_XML_PREFIX) || name.namespace == Some(NS_XML_URI)) {
+            return match attr.value.as_str() {
+                "preserve" => Some(true),
+                "default" => Some(false),
+                _ => None,
+            };
+        }
+    }
+    None
+}
+
+/// Get the qualified name of an element, as written in the source document.
+pub fn qualified_name(name: &OwnedName) -> String {
+    match name.prefix {
+        None => name.local_name.clone(),
+        Some(ref prefix) => format!("{}:{}", prefix, name.local_name),
+    }
+}
+
+/// Check if two names are the same, ignoring the prefixes.
+fn same_name(a: &OwnedName, b: &OwnedName) -> bool {
+    a.local_name == b.local_name && a.namespace == b.namespace
+}
+
+/// Get the qualified names of an element and its ancestors, from the root down.
+fn element_path(elem: &RcElement) -> Vec<String> {
+    let mut path = Vec::new();
+    let mut curr = Some(elem.clone());
+    while let Some(elem) = curr {
+        path.push(qualified_name(&elem.borrow().name));
+        curr = elem.borrow().get_parent();
+    }
+    path.reverse();
+    path
+}
+
+/// Append a text node to the current element.
+/// With `coalesce`, the content is appended to the last child
+/// of the element instead, if it is a text node.
+fn add_text(curr: Option<&RcElement>, content: String, cdata: bool, coalesce: bool, pos: Option<Position>) -> Result<(), BuildError> {
+    let parent = match curr {
+        Some(parent) => parent,
+        None => return Err(BuildError::TextOutsideRoot { position: pos }),
+    };
+    if coalesce {
+        let last = parent.borrow().iter().last().cloned();
+        if let Some(last) = last {
+            if let Node::Text(ref text) = *last.borrow() {
+                text.borrow_mut().append(content.as_str(), cdata);
+                return Ok(());
+            }
+        }
+    }
+    // create the text node with the parent as a weak reference
+    let mut text = if cdata {
+        Text::new_cdata(parent.clone().downgrade(), content)
+    } else {
+        Text::new(parent.clone().downgrade(), content)
+    };
+    text.position = pos;
+    let rctext = rc_text_new(text);
+    // create wrapper node
+    let node = Node::Text(rctext);
+    let rcnode = rc_node_new(node);
+    // add the text node to the parent
+    parent.borrow_mut().add_child(rcnode);
+    Ok(())
+}
+
+/// `build_from_reader` creates a `Document` from a byte source.
+///
+/// The `EventReader` is created with the given configuration, or
+/// `default_config()` which keeps comments. Unlike `build`, this also retains what the parser does not
+/// report, by watching the bytes of the prolog as they are read: the
+/// document type declaration, and whether there is an XML declaration at
+/// all, since the parser makes one up when there is none.
+pub fn build_from_reader<R: Read>(source: R, config: Option<ParserConfig>) -> Result<Document, BuildError> {
+    build_from_reader_with_options(source, config, &BuildOptions::new())
+}
+
+/// `build_from_reader_with_options` creates a `Document` from a byte
+/// source, like `build_from_reader`, according to the given `BuildOptions`.
+///
+/// This is where `max_total_bytes` applies: no more bytes are read from
+/// the source once it is reached.
+pub fn build_from_reader_with_options<R: Read>(source: R, config: Option<ParserConfig>, options: &BuildOptions) -> Result<Document, BuildError> {
+    let exceeded = Rc::new(Cell::new(false));
+    let source = LimitedReader {
+        inner: source,
+        remaining: options.max_total_bytes.unwrap_or(usize::MAX),
+        exceeded: exceeded.clone(),
+    };
+    // The parser only reads UTF-8, fail early rather than on the first
+    // character that does not decode.
+    let mut source = BufReader::new(source);
+    {
+        let head = try!(source.fill_buf());
+        if head.starts_with(b"\xFF\xFE") || head.starts_with(b"\xFE\xFF") {
+            return Err(BuildError::Encoding {
+                message: "UTF-16 input is not supported.".to_string(),
+                position: Some(Position::new(1, 1)),
+            });
+        }
+    }
+    let scanner = Rc::new(RefCell::new(PrologScanner::new()));
+    let sniffer = PrologSniffer {
+        inner: source,
+        scanner: scanner.clone(),
+    };
+    let mut reader = match config {
+        None => EventReader::new_with_config(sniffer, default_config()),
+        Some(config) => EventReader::new_with_config(sniffer, config),
+    };
+    let result = build_with_options(&mut reader, options);
+    // the document looks truncated to the parser
+    if exceeded.get() {
+        return Err(total_bytes_exceeded(options));
+    }
+    let mut doc = try!(result);
+    scanner.borrow().prolog.apply(&mut doc);
+    Ok(doc)
+}
+
+/// The `ParserConfig` of the entry points given no configuration.
+///
+/// It is the default one of the parser, except that comments are kept:
+/// the parser drops them by default, and a document written back would
+/// silently lose them.
+pub fn default_config() -> ParserConfig {
+    ParserConfig::new().ignore_comments(false)
+}
+
+/// The error for a source document longer than `max_total_bytes`.
+pub fn total_bytes_exceeded(options: &BuildOptions) -> BuildError {
+    BuildError::LimitExceeded {
+        limit: "total bytes",
+        max: options.max_total_bytes.unwrap_or(0),
+        position: None,
+        path: Vec::new(),
+    }
+}
+
+/// `build_from_str` creates a `Document` from a string. See `build_from_reader`.
+pub fn build_from_str(source: &str, config: Option<ParserConfig>) -> Result<Document, BuildError> {
+    build_from_reader(source.as_bytes(), config)
+}
+
+/// `build_from_path` creates a `Document` from the file at the given path.
+/// See `build_from_reader`.
+///
+/// Errors are wrapped in `BuildError::File` to tell which file they come from.
+pub fn build_from_path<P: AsRef<Path>>(path: P, config: Option<ParserConfig>) -> Result<Document, BuildError> {
+    let path = path.as_ref();
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) => return Err(BuildError::File(path.to_path_buf(), Box::new(BuildError::Io(err)))),
+    };
+    build_from_reader(file, config).map_err(|err| {
+        BuildError::File(path.to_path_buf(), Box::new(err))
+    })
+}
+
+/// `build_fragment` creates the nodes of a fragment, such as `a<b/>c<d/>`,
+/// which can have any number of top level elements and text.
+///
+/// The namespace bindings in scope in `context`, if given, are in scope
+/// in the fragment. The nodes have no parent. The parser is configured
+/// with `default_config()`.
+pub fn build_fragment(source: &str, context: Option<&Element>) -> Result<Vec<RcNode>, BuildError> {
+    build_fragment_with_options(source, context, &BuildOptions::new())
+}
+
+/// `build_fragment_with_options` creates the nodes of a fragment, like
+/// `build_fragment`, according to the given `BuildOptions`.
+pub fn build_fragment_with_options(source: &str, context: Option<&Element>, options: &BuildOptions) -> Result<Vec<RcNode>, BuildError> {
+    if source.len() > options.max_total_bytes.unwrap_or(usize::MAX) {
+        return Err(total_bytes_exceeded(options));
+    }
+    // The parser needs a single root element, wrap the fragment in one
+    // which declares the bindings of the context.
+    let mut start = format!("<{}", FRAGMENT_WRAPPER);
+    if let Some(context) = context {
+        for (prefix, uri) in context.namespace.0.iter() {
+            if prefix == NS_XML_PREFIX || prefix == NS_XMLNS_PREFIX {
+                continue;
+            }
+            if prefix.is_empty() {
+                start.push_str(&format!(" xmlns=\"{}\"", escape_attribute(uri)));
+            } else {
+                start.push_str(&format!(" xmlns:{}=\"{}\"", prefix, escape_attribute(uri)));
+            }
+        }
+    }
+    start.push('>');
+    let offset = start.chars().count();
+    let wrapped = format!("{}{}</{}>", start, source, FRAGMENT_WRAPPER);
+
+    let mut reader = EventReader::new_with_config(wrapped.as_bytes(), default_config());
+    let mut builder = TreeBuilder::new_fragment(options.clone());
+    let mut depth = 0;
+    // where the wrapper was closed, which must be the end of the input
+    let mut closed = None;
+    // a position in the fragment, without the start tag of the wrapper
+    let unwrap = |row: usize, col: usize| {
+        let mut pos = Position::new(row + 1, col + 1);
+        if pos.line == 1 {
+            pos.column = pos.column.saturating_sub(offset);
+        }
+        pos
+    };
+    loop {
+        // the position where the event starts
+        let pos = unwrap(reader.row(), reader.col());
+        let event = reader.next();
+        if let Some(position) = closed {
+            match event {
+                XmlEvent::EndDocument => break,
+                // the fragment has an end tag for the wrapper
+                _ => {
+                    return Err(BuildError::UnexpectedEndTag {
+                        name: FRAGMENT_WRAPPER.to_string(),
+                        position: Some(position),
+                        path: Vec::new(),
+                    });
+                }
+            }
+        }
+        let event = match event {
+            XmlEvent::StartElement { .. } => {
+                depth += 1;
+                if depth == 1 {
+                    continue;
+                }
+                event
+            }
+            XmlEvent::EndElement { .. } => {
+                depth -= 1;
+                if depth == 0 {
+                    closed = Some(pos);
+                    continue;
+                }
+                event
+            }
+            XmlEvent::EndDocument => break,
+            // keep where the parser found the error
+            XmlEvent::Error(err) => XmlEvent::Error(Error::new(&unwrap(err.row(), err.col()), err.msg().to_string())),
+            event => event,
+        };
+        try!(builder.feed_with_position(event, pos));
+    }
+    builder.finish_fragment()
+}
+
+/// What the bytes of the prolog tell about a document which its events do not.
+#[derive(Default)]
+pub struct Prolog {
+    /// the document starts with an XML declaration
+    pub declaration: bool,
+    pub doctype: Option<Doctype>,
+    pub doctype_position: Option<Position>,
+}
+
+impl Prolog {
+
+    /// Complete a document built from the events of the parser.
+    pub fn apply(&self, doc: &mut Document) {
+        // the parser reports a declaration with default values if there is none
+        if !self.declaration {
+            doc.version = None;
+            doc.encoding = None;
+            doc.standalone = None;
+        }
+        doc.doctype = self.doctype.clone();
+        // the nodes which the parser started reading before it skipped
+        // the document type declaration come before it
+        doc.doctype_index = match self.doctype_position {
+            None => 0,
+            Some(pos) => {
+                doc.prolog.iter().take_while(|node| {
+                    node.borrow().start().map(|start| start < pos).unwrap_or(true)
+                }).count()
+            }
+        };
+    }
+
+}
+
+/// Looks at the bytes of a document as they are read, until the root
+/// element starts, to find its `Prolog`.
+pub struct PrologScanner {
+    buf: Vec<u8>,
+    done: bool,
+    pub prolog: Prolog,
+}
+
+impl PrologScanner {
+
+    pub fn new() -> PrologScanner {
+        PrologScanner {
+            buf: Vec::new(),
+            done: false,
+            prolog: Prolog::default(),
+        }
+    }
+
+    /// Check if the `Prolog` is known, which is once the root element
+    /// started or the document ended. Until then it is the default one.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Look at the next bytes of the document, no bytes meaning its end.
+    pub fn scan(&mut self, bytes: &[u8]) {
+        if self.done {
+            return;
+        }
+        self.buf.extend(bytes.iter().cloned());
+        let done = match scan_prolog(&self.buf) {
+            Scan::Incomplete => bytes.is_empty(),
+            Scan::Absent => true,
+            Scan::Found(decl, start) => {
+                let decl = String::from_utf8_lossy(decl);
+                self.prolog.doctype = Doctype::parse(&decl);
+                self.prolog.doctype_position = Some(byte_position(&self.buf, start));
+                true
+            }
+        };
+        if done {
+            self.prolog.declaration = starts_with_declaration(&self.buf);
+            self.done = true;
+            self.buf = Vec::new();
+        }
+    }
+
+}
+
+/// Passes at most `remaining` bytes through, then ends the input,
+/// setting `exceeded` if there were more.
+struct LimitedReader<R> {
+    inner: R,
+    remaining: usize,
+    exceeded: Rc<Cell<bool>>,
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            let mut byte = [0];
+            if try!(self.inner.read(&mut byte)) > 0 {
+                self.exceeded.set(true);
+            }
+            return Ok(0);
+        }
+        let len = cmp::min(buf.len(), self.remaining);
+        let n = try!(self.inner.read(&mut buf[..len]));
+        self.remaining -= n;
+        Ok(n)
+    }
+
+}
+
+/// Passes bytes through a `PrologScanner`.
+struct PrologSniffer<R> {
+    inner: R,
+    scanner: Rc<RefCell<PrologScanner>>,
+}
+
+impl<R: Read> Read for PrologSniffer<R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = try!(self.inner.read(buf));
+        // reading nothing is only the end of the input if there was room
+        if !buf.is_empty() {
+            self.scanner.borrow_mut().scan(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+}
+
+enum Scan<'a> {
+    /// more bytes are needed
+    Incomplete,
+    /// the root element starts without a document type declaration
+    Absent,
+    /// the document type declaration and where it starts
+    Found(&'a [u8], usize),
+}
+
+/// Look for the document type declaration in the beginning of a document,
+/// skipping over the XML declaration, processing instructions and comments.
+fn scan_prolog(buf: &[u8]) -> Scan {
+    if buf.len() < 3 && b"\xEF\xBB\xBF".starts_with(buf) {
+        return Scan::Incomplete;
+    }
+    let mut i = if buf.starts_with(b"\xEF\xBB\xBF") { 3 } else { 0 };
+    loop {
+        while i < buf.len() && is_whitespace(buf[i]) {
+            i += 1;
+        }
+        let rest = &buf[i..];
+        if rest.len() < 9 && (b"<!DOCTYPE".starts_with(rest) || b"<!--".starts_with(rest)) {
+            return Scan::Incomplete;
+        }
+        if rest.starts_with(b"<?") {
+            match find(buf, i, b"?>") {
+                None => return Scan::Incomplete,
+                Some(end) => i = end + 2,
+            }
+        } else if rest.starts_with(b"<!--") {
+            match find(buf, i, b"-->") {
+                None => return Scan::Incomplete,
+                Some(end) => i = end + 3,
+            }
+        } else if rest.starts_with(b"<!DOCTYPE") {
+            return match doctype_end(buf, i) {
+                None => Scan::Incomplete,
+                Some(end) => Scan::Found(&buf[i..end + 1], i),
+            };
+        } else {
+            return Scan::Absent;
+        }
+    }
+}
+
+/// Find the closing `>` of the document type declaration starting at `start`.
+fn doctype_end(buf: &[u8], start: usize) -> Option<usize> {
+    let mut i = start;
+    let mut quote: Option<u8> = None;
+    let mut in_subset = false;
+    while i < buf.len() {
+        let c = buf[i];
+        match quote {
+            Some(q) => {
+                if c == q {
+                    quote = None;
+                }
+            }
+            None => {
+                if c == b'"' || c == b'\'' {
+                    quote = Some(c);
+                } else if in_subset && buf[i..].starts_with(b"<!--") {
+                    i = match find(buf, i, b"-->") {
+                        None => return None,
+                        Some(end) => end + 2,
+                    };
+                } else if c == b'[' {
+                    in_subset = true;
+                } else if c == b']' {
+                    in_subset = false;
+                } else if c == b'>' && !in_subset {
+                    return Some(i);
+                }
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Get the position of the byte at `offset` in a document.
+fn byte_position(buf: &[u8], offset: usize) -> Position {
+    let start = if buf.starts_with(b"\xEF\xBB\xBF") { 3 } else { 0 };
+    let mut pos = Position::new(1, 1);
+    // count the characters, not their continuation bytes
+    for &c in buf[start..offset].iter() {
+        if c == b'\n' {
+            pos.line += 1;
+            pos.column = 1;
+        } else if c & 0xC0 != 0x80 {
+            pos.column += 1;
+        }
+    }
+    pos
+}
+
+/// Check if a document starts with an XML declaration, `<?xml` being
+/// a reserved processing instruction target.
+fn starts_with_declaration(buf: &[u8]) -> bool {
+    let buf = if buf.starts_with(b"\xEF\xBB\xBF") { &buf[3..] } else { buf };
+    buf.len() > 5 && buf.starts_with(b"<?xml") && (is_whitespace(buf[5]) || buf[5] == b'?')
+}
+
+fn find(buf: &[u8], from: usize, pattern: &[u8]) -> Option<usize> {
+    buf[from..].windows(pattern.len()).position(|w| w == pattern).map(|p| p + from)
+}
+
+fn is_whitespace(c: u8) -> bool {
+    c == b' ' || c == b'\t' || c == b'\r' || c == b'\n'
+}
+
+#[cfg(test)]
+mod tests {
+    use dom::{Doctype, Document, Node, Position, RcNode, RcText, WriterConfig};
+    use error::BuildError;
+    use options::{BuildOptions, WhitespaceMode};
+    use push::PushBuilder;
+    use super::{build_fragment, build_fragment_with_options, build_from_events, build_from_reader, build_from_reader_with_options};
+    use super::{build_from_str, build_lenient, build_partial, build_with_options, TreeBuilder};
+
+    use xml::EventReader;
+    use xml::reader::config::ParserConfig;
+    use xml::attribute::OwnedAttribute;
+    use xml::name::OwnedName;
+    use xml::common::{Error, HasPosition, XmlVersion};
+    use xml::namespace::Namespace;
+    use xml::reader::events::XmlEvent;
+
+    #[test]
+    fn test_doctype() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+                   <!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\">\
+                   <html></html>";
+        let doc = build_from_reader(xml.as_bytes(), None).unwrap();
+
+        {
+            let doctype = doc.doctype.as_ref().unwrap();
+            assert_eq!(doctype.name, "html");
+            assert_eq!(doctype.public_id, Some("-//W3C//DTD XHTML 1.0 Strict//EN".to_string()));
+            assert_eq!(doctype.system_id, Some("http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd".to_string()));
+        }
+        assert_eq!(format!("{}", doc), xml);
+
+        // the document type declaration stays where it was among the prolog nodes
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                   <!-- Generator: Adobe Illustrator -->\n\
+                   <!DOCTYPE svg PUBLIC \"-//W3C//DTD SVG 1.1//EN\" \"http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd\">\n\
+                   <svg/>\n";
+        let config = ParserConfig::new().ignore_comments(false);
+        let doc = build_from_reader(xml.as_bytes(), Some(config)).unwrap();
+        assert_eq!(doc.doctype_index, 1);
+        let mut buf = String::new();
+        doc.write_with(&mut buf, &WriterConfig::new()).unwrap();
+        assert_eq!(buf, xml);
+
+        let mut push = PushBuilder::with_config(ParserConfig::new().ignore_comments(false), BuildOptions::new());
+        for chunk in xml.as_bytes().chunks(7) {
+            push.feed(chunk);
+        }
+        let doc = push.finish().unwrap();
+        assert_eq!(doc.doctype.as_ref().unwrap().name, "svg");
+        assert_eq!(doc.doctype_index, 1);
+
+        let doctype = Doctype::parse("<!DOCTYPE svg SYSTEM 'a\"b.dtd' [ <!ENTITY x \"]>\"> ]>").unwrap();
+        assert_eq!(doctype.system_id, Some("a\"b.dtd".to_string()));
+        assert_eq!(doctype.internal_subset, Some(" <!ENTITY x \"]>\"> ".to_string()));
+        assert_eq!(format!("{}", doctype), "<!DOCTYPE svg SYSTEM 'a\"b.dtd' [ <!ENTITY x \"]>\"> ]>");
+        assert!(Doctype::parse("<!DOCTYPE>").is_none());
+    }
+
+    #[test]
+    fn test_whitespace_options() {
+        let xml = "<root>\n  <a>  x  </a>\n  <b xml:space=\"preserve\">\n    <c>  y  </c>\n  </b>\n</root>";
+        let config = ParserConfig::new().whitespace_to_characters(true);
+        let texts = |options: BuildOptions| -> Vec<String> {
+            let mut reader = EventReader::new_with_config(xml.as_bytes(), config.clone());
+            let doc = build_with_options(&mut reader, &options).unwrap();
+            let root = doc.root.borrow();
+            let mut texts: Vec<String> = root.iter_text().map(|t| t.borrow().content.clone()).collect();
+            for elem in root.iter_elements() {
+                texts.push(elem.borrow().text());
+                for child in elem.borrow().iter_elements() {
+                    texts.push(child.borrow().text());
+                }
+            }
+            texts
+        };
+
+        assert_eq!(texts(BuildOptions::new()), vec!["  x  ", "\n    \n  ", "  y  "]);
+        assert_eq!(texts(BuildOptions::new().whitespace(WhitespaceMode::Trim)), vec!["x", "\n    \n  ", "  y  "]);
+        assert_eq!(texts(BuildOptions::new().whitespace(WhitespaceMode::Trim).respect_xml_space(false)), vec!["x", "", "y"]);
+        assert_eq!(texts(BuildOptions::new().whitespace(WhitespaceMode::Preserve)).len(), 6);
+    }
+
+    #[test]
+    fn test_coalesce_text() {
+        let xml = "<root>a<![CDATA[<b>]]>c<sep/>d</root>";
+        let options = BuildOptions::new().coalesce_text(true);
+        let mut reader = EventReader::new(xml.as_bytes());
+        let doc = build_with_options(&mut reader, &options).unwrap();
+        let root = doc.root.borrow();
+
+        let v: Vec<RcText> = root.iter_text().collect();
+        assert_eq!(v.len(), 2);
+        assert_eq!(v[0].borrow().content, "a<b>c");
+        assert!(!v[0].borrow().cdata);
+    }
+
+    #[test]
+    fn test_from_path() {
+        let doc = Document::from_path("data/ex1.xml", None).unwrap();
+        assert_eq!(doc.root.borrow().find("elem").len(), 1);
+
+        let doc: Document = "<root/>".parse().unwrap();
+        assert_eq!(format!("{}", *doc.root.borrow()), "<root></root>");
+
+        match Document::from_path("data/missing.xml", None) {
+            Err(BuildError::File(ref path, ref err)) => {
+                assert!(path.ends_with("missing.xml"));
+                match **err {
+                    BuildError::Io(_) => {}
+                    _ => panic!("Expected an I/O error."),
+                }
+            }
+            _ => panic!("Expected a file error."),
+        }
+    }
+
+    #[test]
+    fn test_positions() {
+        let xml = "<root>\n  <item>text</item>\n  <other/>\n</root>";
+        let doc = build_from_str(xml, None).unwrap();
+        let root = doc.root.borrow();
+
+        assert_eq!(root.start.unwrap().line, 1);
+        assert_eq!(root.end, Some(Position::new(4, 1)));
+        let nodes: Vec<RcNode> = root.iter().cloned().collect();
+        assert_eq!(nodes[0].borrow().start(), Some(Position::new(2, 3)));
+        assert_eq!(nodes[0].borrow().end(), Some(Position::new(2, 13)));
+        assert_eq!(nodes[1].borrow().start(), Some(Position::new(3, 3)));
+        let item = root.find("item")[0].clone();
+        let text = item.borrow().iter_text().next().unwrap();
+        assert_eq!(text.borrow().position, Some(Position::new(2, 9)));
+
+        // text spanning lines starts where it starts, not where it ends
+        let doc = build_from_str("<root><p>two\nlines</p>\n</root>", None).unwrap();
+        let p = doc.root.borrow().find("p")[0].clone();
+        let p = p.borrow();
+        assert_eq!(p.end, Some(Position::new(2, 6)));
+        let text = p.iter_text().next().unwrap();
+        assert_eq!(text.borrow().position, Some(Position::new(1, 10)));
+    }
+
+    #[test]
+    fn test_error_context() {
+        let xml = "<root>\n  <item>\n    <a></b>\n  </item>\n</root>";
+        let err = build_from_str(xml, None).err().unwrap();
+
+        assert_eq!(err.path(), ["root", "item", "a"]);
+        assert_eq!(err.position().unwrap().line, 3);
+        let rendered = err.render(xml);
+        assert!(rendered.contains("3 |     <a></b>\n"));
+        assert!(rendered.ends_with("^\n"));
+
+        match build_from_reader(&b"\xFF\xFE<\x00"[..], None) {
+            Err(BuildError::Encoding { .. }) => {}
+            _ => panic!("Expected an encoding error."),
         }
     }
+
+    /// A xorshift generator, so that the random tests are the same on every run.
+    struct Rng(u32);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as usize % n
+        }
+    }
+
+    struct Nowhere;
+
+    impl HasPosition for Nowhere {
+        fn row(&self) -> usize { 0 }
+        fn col(&self) -> usize { 0 }
+    }
+
+    fn random_name(rng: &mut Rng) -> OwnedName {
+        let (local_name, namespace, prefix) = match rng.below(7) {
+            0 => ("a", None, None),
+            1 => ("b", None, None),
+            2 => ("a", Some("urn:x"), Some("x")),
+            3 => ("a", Some("urn:d"), None),
+            4 => ("b", Some("urn:y"), Some("x")),
+            5 => ("space", Some("http://www.w3.org/XML/1998/namespace"), Some("xml")),
+            _ => ("long-name", Some("urn:long"), Some("long-prefix")),
+        };
+        OwnedName {
+            local_name: local_name.to_string(),
+            namespace: namespace.map(|s| s.to_string()),
+            prefix: prefix.map(|s| s.to_string()),
+        }
+    }
+
+    fn random_text(rng: &mut Rng) -> String {
+        ["", " ", "\n  ", "x", " y ", "]]>", "--", "preserve", "default", "a longer text"][rng.below(10)].to_string()
+    }
+
+    fn random_namespace(rng: &mut Rng) -> Namespace {
+        let mut namespace = Namespace::empty();
+        for _ in 0..rng.below(4) {
+            let (prefix, uri) = [
+                ("x", "urn:x"),
+                ("x", "urn:y"),
+                ("", "urn:d"),
+                ("", ""),
+                ("xml", "http://www.w3.org/XML/1998/namespace"),
+                ("long-prefix", "urn:long"),
+            ][rng.below(6)];
+            namespace.0.insert(prefix.to_string(), uri.to_string());
+        }
+        namespace
+    }
+
+    fn random_event(rng: &mut Rng) -> XmlEvent {
+        match rng.below(11) {
+            0 => XmlEvent::StartDocument {
+                version: XmlVersion::Version10,
+                encoding: "UTF-8".to_string(),
+                standalone: None,
+            },
+            1 => XmlEvent::EndDocument,
+            2 => {
+                let data = if rng.below(2) == 0 { None } else { Some(random_text(rng)) };
+                XmlEvent::ProcessingInstruction { name: "pi".to_string(), data: data }
+            }
+            3 | 4 => {
+                let mut attributes = Vec::new();
+                for _ in 0..rng.below(3) {
+                    attributes.push(OwnedAttribute {
+                        name: random_name(rng),
+                        value: random_text(rng),
+                    });
+                }
+                XmlEvent::StartElement {
+                    name: random_name(rng),
+                    attributes: attributes,
+                    namespace: random_namespace(rng),
+                }
+            }
+            5 | 6 => XmlEvent::EndElement { name: random_name(rng) },
+            7 => XmlEvent::CData(random_text(rng)),
+            8 => XmlEvent::Characters(random_text(rng)),
+            9 => XmlEvent::Whitespace(" ".to_string()),
+            _ => {
+                if rng.below(4) == 0 {
+                    XmlEvent::Error(Error::new(&Nowhere, "error".to_string()))
+                } else {
+                    XmlEvent::Comment(random_text(rng))
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_never_panics() {
+        let mut rng = Rng(0x9E3779B9);
+        let options = [
+            BuildOptions::new(),
+            BuildOptions::new().whitespace(WhitespaceMode::Preserve).coalesce_text(true),
+            BuildOptions::new().whitespace(WhitespaceMode::Trim).respect_xml_space(false),
+            BuildOptions::new().max_depth(2).max_nodes(5).max_attributes(1),
+            BuildOptions::new().max_namespaces(1).max_text_length(2).max_name_length(2),
+            BuildOptions::new().coalesce_text(true).max_nodes(3).max_text_length(4),
+        ];
+        for i in 0..10000 {
+            let len = rng.below(24);
+            // every mode with every set of options
+            let mode = (i / options.len()) % 3;
+            let options = options[i % options.len()].clone();
+            let mut builder = match mode {
+                0 => TreeBuilder::with_options(options),
+                1 => TreeBuilder::new_lenient(options),
+                _ => TreeBuilder::new_fragment(options),
+            };
+            for col in 0..len {
+                if builder.feed_with_position(random_event(&mut rng), Position::new(1, col + 1)).is_err() {
+                    break;
+                }
+                if rng.below(8) == 0 {
+                    if let Some(doc) = builder.partial() {
+                        doc.to_string();
+                    }
+                }
+            }
+            match mode {
+                0 => {
+                    if let Ok(doc) = builder.finish() {
+                        doc.to_string();
+                    }
+                }
+                1 => {
+                    if let Ok((doc, _)) = builder.finish_lenient() {
+                        doc.to_string();
+                    }
+                }
+                _ => {
+                    if let Ok(nodes) = builder.finish_fragment() {
+                        for node in nodes.iter() {
+                            node.borrow().to_string();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_balanced_events() {
+        let mut rng = Rng(0x2545F491);
+        for _ in 0..1000 {
+            let mut events = Vec::new();
+            let mut open = vec![random_name(&mut rng)];
+            events.push(XmlEvent::StartElement {
+                name: open[0].clone(),
+                attributes: Vec::new(),
+                namespace: Namespace::empty(),
+            });
+            while !open.is_empty() {
+                match rng.below(4) {
+                    0 if events.len() < 32 => {
+                        let name = random_name(&mut rng);
+                        open.push(name.clone());
+                        events.push(XmlEvent::StartElement {
+                            name: name,
+                            attributes: Vec::new(),
+                            namespace: Namespace::empty(),
+                        });
+                    }
+                    0 | 1 => events.push(XmlEvent::EndElement { name: open.pop().unwrap() }),
+                    _ => {
+                        let event = random_event(&mut rng);
+                        match event {
+                            XmlEvent::StartElement { .. } | XmlEvent::EndElement { .. } |
+                            XmlEvent::StartDocument { .. } | XmlEvent::EndDocument | XmlEvent::Error(_) => {}
+                            _ => events.push(event),
+                        }
+                    }
+                }
+            }
+            assert!(build_from_events(events).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let name = OwnedName { local_name: "a".to_string(), namespace: None, prefix: None };
+        let start = XmlEvent::StartElement {
+            name: name.clone(),
+            attributes: Vec::new(),
+            namespace: Namespace::empty(),
+        };
+        let end = XmlEvent::EndElement { name: name.clone() };
+        let build = build_from_events::<Vec<XmlEvent>>;
+
+        match build(vec![start.clone(), end.clone(), start.clone()]) {
+            Err(BuildError::MultipleRoots { .. }) => {}
+            _ => panic!("Expected a second root to be rejected."),
+        }
+        match build(vec![XmlEvent::Characters("x".to_string()), start.clone()]) {
+            Err(BuildError::TextOutsideRoot { .. }) => {}
+            _ => panic!("Expected text outside of the root to be rejected."),
+        }
+        match build(vec![start.clone(), start.clone(), end.clone()]) {
+            Err(BuildError::UnclosedElement { path, .. }) => assert_eq!(path, ["a"]),
+            _ => panic!("Expected an unclosed element to be rejected."),
+        }
+
+        let other = OwnedName { local_name: "b".to_string(), namespace: None, prefix: None };
+        match build(vec![start.clone(), start.clone(), XmlEvent::EndElement { name: other }]) {
+            Err(err @ BuildError::MismatchedTag { .. }) => {
+                assert_eq!(err.path(), ["a", "a"]);
+                assert_eq!(err.to_string(), "Mismatched end tag </b>, expected </a>. (in /a/a)");
+            }
+            _ => panic!("Expected a mismatched end tag to be rejected."),
+        }
+        match build(vec![end.clone()]) {
+            Err(BuildError::UnexpectedEndTag { .. }) => {}
+            _ => panic!("Expected a stray end tag to be rejected."),
+        }
+    }
+
+    #[test]
+    fn test_build_from_events() {
+        let xml = "<root><!-- a --><item>aa</item><!-- b --><item>bb</item></root>";
+        let config = ParserConfig::new().ignore_comments(false);
+        let mut reader = EventReader::new_with_config(xml.as_bytes(), config);
+        let events = reader.events().filter(|event| match *event {
+            XmlEvent::Comment(_) => false,
+            _ => true,
+        });
+        let doc = build_from_events(events).unwrap();
+        assert_eq!(doc.root.borrow().to_string(), "<root><item>aa</item><item>bb</item></root>");
+        assert_eq!(doc.root.borrow().start, None);
+
+        let mut builder = TreeBuilder::new();
+        let name = OwnedName { local_name: "a".to_string(), namespace: None, prefix: None };
+        builder.feed(XmlEvent::StartElement {
+            name: name.clone(),
+            attributes: Vec::new(),
+            namespace: Namespace::empty(),
+        }).unwrap();
+        builder.feed_with_position(XmlEvent::Characters("x".to_string()), Position::new(1, 4)).unwrap();
+        builder.feed(XmlEvent::EndElement { name: name }).unwrap();
+        let doc = builder.finish().unwrap();
+        assert_eq!(doc.root.borrow().to_string(), "<a>x</a>");
+        let root = doc.root.borrow();
+        match *root.iter().next().unwrap().borrow() {
+            Node::Text(ref text) => assert_eq!(text.borrow().position, Some(Position::new(1, 4))),
+            _ => panic!("Expected a text node."),
+        };
+    }
+
+    #[test]
+    fn test_fragment() {
+        let nodes = build_fragment("a<b/>c<d>e</d>", None).unwrap();
+        assert_eq!(nodes.len(), 4);
+        let written: Vec<String> = nodes.iter().map(|node| node.borrow().to_string()).collect();
+        assert_eq!(written, ["a", "<b></b>", "c", "<d>e</d>"]);
+        assert!(nodes[1].borrow().get_parent().is_none());
+        assert_eq!(nodes[1].borrow().start(), Some(Position::new(1, 2)));
+
+        let nodes = build_fragment("text only", None).unwrap();
+        assert_eq!(nodes.len(), 1);
+
+        let doc = build_from_str("<root xmlns=\"urn:default\" xmlns:x=\"urn:x\"/>", None).unwrap();
+        let nodes = build_fragment("<x:a/><b/>", Some(&doc.root.borrow())).unwrap();
+        match *nodes[0].borrow() {
+            Node::Element(ref elem) => assert_eq!(elem.borrow().name.namespace, Some("urn:x".to_string())),
+            _ => panic!("Expected an element."),
+        };
+        match *nodes[1].borrow() {
+            Node::Element(ref elem) => assert_eq!(elem.borrow().name.namespace, Some("urn:default".to_string())),
+            _ => panic!("Expected an element."),
+        };
+
+        match build_fragment("a<b>", None) {
+            Err(err) => assert_eq!(err.path(), ["b"]),
+            Ok(_) => panic!("Expected an error."),
+        }
+        // parser errors are where the parser found them, as in a document
+        let found = build_fragment("abc & def", None).err().unwrap().position().unwrap();
+        let expected = build_from_str("<r>abc & def</r>", None).err().unwrap().position().unwrap();
+        assert_eq!(found, Position::new(1, expected.column - 3));
+        assert!(found.column > 1);
+        for source in ["a</xml-tree-fragment>", "a</xml-tree-fragment><xml-tree-fragment>b"].iter() {
+            match build_fragment(source, None) {
+                Err(BuildError::UnexpectedEndTag { position, .. }) => assert_eq!(position, Some(Position::new(1, 2))),
+                _ => panic!("Expected the end tag of the wrapper to be rejected."),
+            }
+        }
+
+        let options = BuildOptions::new().whitespace(WhitespaceMode::Trim).max_depth(1);
+        let nodes = build_fragment_with_options(" a <b/> ", None, &options).unwrap();
+        let written: Vec<String> = nodes.iter().map(|node| node.borrow().to_string()).collect();
+        assert_eq!(written, ["a", "<b></b>"]);
+        assert!(build_fragment_with_options("a<b><c/></b>", None, &options).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let xml = "<root a=\"1\" b=\"2\"><item><sub>text</sub></item><item/><!-- c --></root>";
+        let build = |options: BuildOptions| {
+            let config = ParserConfig::new().ignore_comments(false);
+            build_with_options(&mut EventReader::new_with_config(xml.as_bytes(), config), &options)
+        };
+
+        assert!(build(BuildOptions::new().max_depth(3).max_nodes(6).max_attributes(2)).is_ok());
+        assert!(build(BuildOptions::new().max_text_length(4).max_name_length(4)).is_ok());
+
+        let cases = vec![
+            (BuildOptions::new().max_depth(2), "depth", vec!["root", "item"]),
+            (BuildOptions::new().max_nodes(5), "nodes", vec!["root"]),
+            (BuildOptions::new().max_attributes(1), "attributes", vec![]),
+            (BuildOptions::new().max_text_length(3), "text length", vec!["root", "item", "sub"]),
+            (BuildOptions::new().max_name_length(3), "name length", vec![]),
+        ];
+        for (options, expected, path) in cases.into_iter() {
+            match build(options) {
+                Err(BuildError::LimitExceeded { limit, path: ref found, .. }) => {
+                    assert_eq!(limit, expected);
+                    assert_eq!(*found, path);
+                }
+                _ => panic!("Expected the {} limit to be exceeded.", expected),
+            }
+        }
+
+        // prefixes, attribute values, comments and instructions are checked too
+        let xml = "<long:a xmlns:long=\"urn:a\" b=\"value\"><?pi pi-data?><!-- comment --></long:a>";
+        let build = |options: BuildOptions| {
+            let config = ParserConfig::new().ignore_comments(false);
+            build_with_options(&mut EventReader::new_with_config(xml.as_bytes(), config), &options)
+        };
+        let namespaces = build(BuildOptions::new()).unwrap().root.borrow().namespace.0.len();
+        assert!(build(BuildOptions::new().max_name_length(4).max_text_length(9).max_namespaces(namespaces)).is_ok());
+        let cases = vec![
+            (BuildOptions::new().max_name_length(3), "name length"),
+            (BuildOptions::new().max_text_length(4), "text length"),
+            (BuildOptions::new().max_text_length(6).max_name_length(4), "text length"),
+            (BuildOptions::new().max_text_length(8).max_name_length(4), "text length"),
+            (BuildOptions::new().max_namespaces(namespaces - 1), "namespaces"),
+        ];
+        for (options, expected) in cases.into_iter() {
+            match build(options) {
+                Err(BuildError::LimitExceeded { limit, .. }) => assert_eq!(limit, expected),
+                _ => panic!("Expected the {} limit to be exceeded.", expected),
+            }
+        }
+
+        // dropped whitespace and coalesced text are not nodes
+        let xml = "<root>\n  <a>x<![CDATA[y]]></a>\n  <b/>\n</root>";
+        let options = BuildOptions::new().coalesce_text(true).max_nodes(4);
+        assert!(build_with_options(&mut EventReader::new(xml.as_bytes()), &options).is_ok());
+        let options = options.max_nodes(3);
+        assert!(build_with_options(&mut EventReader::new(xml.as_bytes()), &options).is_err());
+
+        let options = BuildOptions::new().max_total_bytes(xml.len());
+        assert!(build_from_reader_with_options(xml.as_bytes(), None, &options).is_ok());
+        let options = options.max_total_bytes(xml.len() - 1);
+        match build_from_reader_with_options(xml.as_bytes(), None, &options) {
+            Err(BuildError::LimitExceeded { limit, .. }) => assert_eq!(limit, "total bytes"),
+            _ => panic!("Expected the total bytes limit to be exceeded."),
+        }
+        let mut push = PushBuilder::with_config(ParserConfig::new(), options);
+        for chunk in xml.as_bytes().chunks(8) {
+            push.feed(chunk);
+        }
+        assert!(push.is_failed());
+    }
+
+    #[test]
+    fn test_lenient() {
+        let xml = "<root><item>aa</item><item>bb<sub>";
+        let mut reader = EventReader::new(xml.as_bytes());
+        let (doc, diagnostics) = build_lenient(&mut reader, &BuildOptions::new()).unwrap();
+        assert_eq!(doc.root.borrow().to_string(), "<root><item>aa</item><item>bb<sub></sub></item></root>");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[1].path(), ["root", "item", "sub"]);
+
+        // the parser stops at a mismatched end tag, the document ends there
+        let xml = "<root><a>x</a><b><c>y</b><d/></root>";
+        let mut reader = EventReader::new(xml.as_bytes());
+        let (doc, diagnostics) = build_lenient(&mut reader, &BuildOptions::new()).unwrap();
+        assert_eq!(doc.root.borrow().to_string(), "<root><a>x</a><b><c>y</c></b></root>");
+        match diagnostics[0] {
+            BuildError::ParserError(_, ref path) => assert_eq!(*path, ["root", "b", "c"]),
+            _ => panic!("Expected a parser error."),
+        }
+        assert_eq!(diagnostics.len(), 2);
+
+        let name = |local_name: &str| OwnedName { local_name: local_name.to_string(), namespace: None, prefix: None };
+        let start = |local_name: &str| XmlEvent::StartElement {
+            name: name(local_name),
+            attributes: Vec::new(),
+            namespace: Namespace::empty(),
+        };
+        let end = |local_name: &str| XmlEvent::EndElement { name: name(local_name) };
+        let events = vec![
+            XmlEvent::Characters("lost".to_string()),
+            start("root"), start("a"), start("b"), end("x"), end("a"),
+            XmlEvent::Characters("kept".to_string()), end("root"),
+            start("other"), start("root"), end("root"), end("other"),
+        ];
+        let mut builder = TreeBuilder::new_lenient(BuildOptions::new());
+        for event in events.into_iter() {
+            builder.feed(event).unwrap();
+        }
+        let (doc, diagnostics) = builder.finish_lenient().unwrap();
+        assert_eq!(doc.root.borrow().to_string(), "<root><a><b></b></a>kept</root>");
+        let kinds: Vec<&str> = diagnostics.iter().map(|err| match *err {
+            BuildError::TextOutsideRoot { .. } => "text",
+            BuildError::UnexpectedEndTag { .. } => "stray",
+            BuildError::MismatchedTag { .. } => "mismatch",
+            BuildError::MultipleRoots { .. } => "root",
+            _ => "other",
+        }).collect();
+        assert_eq!(kinds, ["text", "stray", "mismatch", "root"]);
+    }
+
+    #[test]
+    fn test_partial() {
+        let xml = "<root><a>x</a><b><c>y</b></root>";
+        let mut reader = EventReader::new(xml.as_bytes());
+        let err = build_partial(&mut reader, &BuildOptions::new()).err().unwrap();
+        assert_eq!(err.open, ["root", "b", "c"]);
+        let doc = err.document.unwrap();
+        assert_eq!(doc.root.borrow().to_string(), "<root><a>x</a><b><c>y</c></b></root>");
+
+        let mut reader = EventReader::new("<?xml version=\"1.0\"?>".as_bytes());
+        let err = build_partial(&mut reader, &BuildOptions::new()).err().unwrap();
+        assert!(err.document.is_none());
+        assert!(err.open.is_empty());
+
+        let mut reader = EventReader::new("<root/>".as_bytes());
+        assert!(build_partial(&mut reader, &BuildOptions::new()).is_ok());
+    }
+
 }
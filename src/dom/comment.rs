@@ -0,0 +1,123 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::iter::Iterator;
+use std::rc::Rc;
+use std::slice::Iter;
+
+use dom::{self, Node, Position, RcNode, RcElement, WeakElement};
+
+/// Describes a comment node of the DOM tree.
+pub struct Comment {
+    /// parent element
+    pub parent: Option<WeakElement>,
+    /// the comment's content, without the `<!--` and `-->` delimiters
+    pub content: String,
+    /// position where it starts in the source document
+    pub position: Option<Position>,
+}
+
+impl Comment {
+
+    pub fn new(parent: WeakElement, content: String) -> Comment {
+        Comment {
+            parent: Some(parent),
+            content: content,
+            position: None,
+        }
+    }
+
+    pub fn new_root(content: String) -> Comment {
+        Comment {
+            parent: None,
+            content: content,
+            position: None,
+        }
+    }
+
+    /// Try to get the parent element.
+    /// Returns `None` if the parent is `None`
+    /// or if the upgrade from `Weak` to `Rc` fails.
+    pub fn get_parent(&self) -> Option<RcElement> {
+        match self.parent {
+            None => None,
+            Some(ref parent) => {
+                parent.clone().upgrade()
+            }
+        }
+    }
+
+    /// Get the comment as it should be written in a document.
+    pub fn serialize(&self) -> String {
+        dom::util::comment_section(self.content.as_str())
+    }
+
+    /// Format the Comment in a pretty way.
+    pub fn format_pretty<W: fmt::Write>(&self, w: &mut W, indent: usize, inc: usize) -> fmt::Result {
+        let padding = dom::util::padding(indent, inc);
+        try!(write!(w, "{}{}\n", padding, self.serialize()));
+        Ok(())
+    }
+
+}
+
+impl fmt::Debug for Comment {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut buf = String::new();
+        try!(self.format_pretty(&mut buf, 0, 2));
+        f.write_str(buf.as_str())
+    }
+
+}
+
+impl fmt::Display for Comment {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.serialize())
+    }
+
+}
+
+/// Iterator for comment nodes.
+pub struct CommentIterator<'a> {
+    source: Box<Iter<'a, RcNode>>,
+}
+
+impl<'a> CommentIterator<'a> {
+
+    pub fn new<'b>(source: Box<Iter<'b, RcNode>>) -> CommentIterator<'b> {
+        CommentIterator {
+            source: source,
+        }
+    }
+
+}
+
+impl<'a> Iterator for CommentIterator<'a> {
+
+    type Item = RcComment;
+
+    fn next(&mut self) -> Option<RcComment> {
+        loop {
+            let it = self.source.next();
+            match it {
+                None => return None,
+                Some(node) => {
+                    match *node.borrow() {
+                        Node::Comment(ref comment) => return Some(comment.clone()),
+                        _ => continue,
+                    }
+                }
+            }
+        }
+    }
+
+}
+
+/// A comment node with shared ownership.
+pub type RcComment = Rc<RefCell<Comment>>;
+
+/// Handy constructor for RcComment.
+pub fn rc_comment_new(comment: Comment) -> RcComment {
+    Rc::new(RefCell::new(comment))
+}
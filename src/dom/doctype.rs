@@ -0,0 +1,177 @@
+use std::fmt;
+
+macro_rules! try_opt {
+    ($e:expr) => (match $e { Some(v) => v, None => return None })
+}
+
+/// Describes the document type declaration of a Document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Doctype {
+    /// name of the root element
+    pub name: String,
+    /// public identifier of the external subset
+    pub public_id: Option<String>,
+    /// system identifier (URI) of the external subset
+    pub system_id: Option<String>,
+    /// internal subset, verbatim, without the enclosing brackets
+    pub internal_subset: Option<String>,
+}
+
+impl Doctype {
+
+    pub fn new(name: String) -> Doctype {
+        Doctype {
+            name: name,
+            public_id: None,
+            system_id: None,
+            internal_subset: None,
+        }
+    }
+
+    /// Parse a document type declaration, from `<!DOCTYPE` to the final `>`.
+    /// Returns `None` if the declaration is malformed.
+    pub fn parse(decl: &str) -> Option<Doctype> {
+        let mut parser = Parser { rest: decl };
+
+        if !parser.eat("<!DOCTYPE") || !parser.skip_whitespace() {
+            return None;
+        }
+        let mut doctype = Doctype::new(parser.name().to_string());
+        if doctype.name.is_empty() {
+            return None;
+        }
+
+        let had_space = parser.skip_whitespace();
+        if had_space && parser.eat("PUBLIC") {
+            if !parser.skip_whitespace() {
+                return None;
+            }
+            doctype.public_id = Some(try_opt!(parser.quoted()).to_string());
+            if !parser.skip_whitespace() {
+                return None;
+            }
+            doctype.system_id = Some(try_opt!(parser.quoted()).to_string());
+            parser.skip_whitespace();
+        } else if had_space && parser.eat("SYSTEM") {
+            if !parser.skip_whitespace() {
+                return None;
+            }
+            doctype.system_id = Some(try_opt!(parser.quoted()).to_string());
+            parser.skip_whitespace();
+        }
+
+        if parser.eat("[") {
+            doctype.internal_subset = Some(try_opt!(parser.subset()).to_string());
+            parser.skip_whitespace();
+        }
+
+        if parser.eat(">") && parser.rest.is_empty() {
+            Some(doctype)
+        } else {
+            None
+        }
+    }
+
+}
+
+impl fmt::Display for Doctype {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "<!DOCTYPE {}", self.name));
+        match (&self.public_id, &self.system_id) {
+            (&Some(ref public_id), &Some(ref system_id)) => {
+                try!(write!(f, " PUBLIC {} {}", quote(public_id), quote(system_id)));
+            }
+            (&None, &Some(ref system_id)) => {
+                try!(write!(f, " SYSTEM {}", quote(system_id)));
+            }
+            _ => {}
+        }
+        if let Some(ref subset) = self.internal_subset {
+            try!(write!(f, " [{}]", subset));
+        }
+        write!(f, ">")
+    }
+
+}
+
+/// Quote a literal, with single quotes if it contains double quotes.
+fn quote(literal: &str) -> String {
+    if literal.contains('"') {
+        format!("'{}'", literal)
+    } else {
+        format!("\"{}\"", literal)
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest.starts_with(token) {
+            self.rest = &self.rest[token.len()..];
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skip whitespace, returns true if there was any.
+    fn skip_whitespace(&mut self) -> bool {
+        let trimmed = self.rest.trim_left();
+        let skipped = trimmed.len() != self.rest.len();
+        self.rest = trimmed;
+        skipped
+    }
+
+    fn name(&mut self) -> &'a str {
+        let end = self.rest.find(|c: char| c.is_whitespace() || c == '[' || c == '>').unwrap_or(self.rest.len());
+        let name = &self.rest[..end];
+        self.rest = &self.rest[end..];
+        name
+    }
+
+    fn quoted(&mut self) -> Option<&'a str> {
+        let quote = match self.rest.chars().next() {
+            Some(c) if c == '"' || c == '\'' => c,
+            _ => return None,
+        };
+        let end = try_opt!(self.rest[1..].find(quote)) + 1;
+        let literal = &self.rest[1..end];
+        self.rest = &self.rest[end + 1..];
+        Some(literal)
+    }
+
+    /// Read the internal subset up to its closing bracket, skipping over
+    /// quoted literals and comments which may contain a bracket.
+    fn subset(&mut self) -> Option<&'a str> {
+        let bytes = self.rest.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b']' => {
+                    let subset = &self.rest[..i];
+                    self.rest = &self.rest[i + 1..];
+                    return Some(subset);
+                }
+                b'"' | b'\'' => {
+                    let quote = bytes[i];
+                    i += 1;
+                    while i < bytes.len() && bytes[i] != quote {
+                        i += 1;
+                    }
+                }
+                b'<' if self.rest[i..].starts_with("<!--") => {
+                    i += try_opt!(self.rest[i..].find("-->")) + 2;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        None
+    }
+
+}
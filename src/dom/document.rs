@@ -1,8 +1,23 @@
-use std::fmt;
+use std::cmp;
+use std::fmt::{self, Write};
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
 
+use builder;
+
+use dom::doctype::Doctype;
 use dom::element::RcElement;
+use dom::events::EventIterator;
+use dom::node::{Node, RcNode, rc_node_new};
+use dom::encoding::{Encoding, EncodingWriter};
+use dom::writer::{Writer, WriterConfig, XmlDeclaration, is_blank};
+
+use error::BuildError;
 
 use xml::common::XmlVersion;
+use xml::reader::config::ParserConfig;
+use xml::namespace::Namespace;
 
 /// Describes an XML Document.
 pub struct Document {
@@ -10,33 +25,166 @@ pub struct Document {
     pub version: Option<XmlVersion>,
     // document encoding
     pub encoding: Option<String>,
+    // document standalone declaration
+    pub standalone: Option<bool>,
+    // document type declaration
+    pub doctype: Option<Doctype>,
+    // number of prolog nodes before the document type declaration
+    pub doctype_index: usize,
+    // comments, processing instructions and whitespace before the root element
+    pub prolog: Vec<RcNode>,
     // root element
     pub root: RcElement,
+    // comments, processing instructions and whitespace after the root element
+    pub epilog: Vec<RcNode>,
 }
 
 impl Document {
 
-    fn print_header(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.version.is_some() || self.encoding.is_some() {
-            try!(write!(f, "<?xml "));
-            if self.version.is_some() {
-                try!(write!(f, "version=\"{}\"", self.version.as_ref().unwrap()));
-            }if self.encoding.is_some() {
-                try!(write!(f, "encoding=\"{}\"", self.encoding.as_ref().unwrap()));
+    /// Create a Document from a string, using the given parser
+    /// configuration or `default_config()`, which keeps comments.
+    pub fn from_str_with_config(s: &str, config: Option<ParserConfig>) -> Result<Document, BuildError> {
+        builder::build_from_str(s, config)
+    }
+
+    /// Create a Document from a byte source, using the given parser
+    /// configuration or `default_config()`, which keeps comments.
+    pub fn from_reader<R: Read>(source: R, config: Option<ParserConfig>) -> Result<Document, BuildError> {
+        builder::build_from_reader(source, config)
+    }
+
+    /// Create a Document from the file at the given path, using the given
+    /// parser configuration or `default_config()`, which keeps comments.
+    /// Errors carry the path.
+    pub fn from_path<P: AsRef<Path>>(path: P, config: Option<ParserConfig>) -> Result<Document, BuildError> {
+        builder::build_from_path(path, config)
+    }
+
+    /// Get the top level nodes of the Document in order:
+    /// the prolog, the root element and the epilog.
+    pub fn children(&self) -> Vec<RcNode> {
+        let mut children = Vec::with_capacity(self.prolog.len() + 1 + self.epilog.len());
+        children.extend(self.prolog.iter().cloned());
+        children.push(rc_node_new(Node::Element(self.root.clone())));
+        children.extend(self.epilog.iter().cloned());
+        children
+    }
+
+    /// Create an iterator over the events describing the Document.
+    pub fn events(&self) -> EventIterator {
+        EventIterator::new_document(self)
+    }
+
+    /// Check if the source document had an XML declaration.
+    pub fn has_declaration(&self) -> bool {
+        self.version.is_some() || self.encoding.is_some() || self.standalone.is_some()
+    }
+
+    /// Write the XML declaration. The version is required and
+    /// defaults to 1.0, the other pseudo-attributes are optional.
+    fn print_header<W: fmt::Write>(&self, f: &mut W) -> fmt::Result {
+        let version = self.version.unwrap_or(XmlVersion::Version10);
+        try!(write!(f, "<?xml version=\"{}\"", version));
+        if let Some(ref encoding) = self.encoding {
+            try!(write!(f, " encoding=\"{}\"", encoding));
+        }
+        if let Some(standalone) = self.standalone {
+            try!(write!(f, " standalone=\"{}\"", if standalone { "yes" } else { "no" }));
+        }
+        write!(f, "?>")
+    }
+
+    /// Write the Document according to the given configuration.
+    pub fn write_with<W: fmt::Write>(&self, w: &mut W, config: &WriterConfig) -> fmt::Result {
+        let mut writer = Writer::new(w, config);
+        let declaration = match config.xml_declaration {
+            XmlDeclaration::Auto => self.has_declaration(),
+            XmlDeclaration::Always => true,
+            XmlDeclaration::Never => false,
+        };
+        if declaration {
+            try!(self.print_header(&mut writer));
+            try!(writer.newline(0));
+        }
+        let doctype_index = cmp::min(self.doctype_index, self.prolog.len());
+        for (i, node) in self.children().iter().enumerate() {
+            if i == doctype_index {
+                if let Some(ref doctype) = self.doctype {
+                    try!(write!(writer, "{}", doctype));
+                    try!(writer.newline(0));
+                }
+            }
+            // indentation replaces the whitespace between top level nodes
+            if config.perform_indent && is_blank(node) {
+                continue;
             }
-            try!(write!(f, "?>"));
+            try!(writer.write_node(&*node.borrow(), &Namespace::empty(), 0));
+            try!(writer.newline(0));
         }
         Ok(())
     }
 
+    /// Write the Document to an `io::Write`, in the encoding it declares.
+    ///
+    /// The output is transcoded and written as it is produced, wrap the
+    /// writer in a `BufWriter` to avoid many small writes. UTF-8 is used
+    /// when the document does not declare an encoding. UTF-16 output starts
+    /// with a byte order mark. Characters that ISO-8859-1 and US-ASCII
+    /// cannot represent are written as character references in text and
+    /// attribute values, CDATA sections are split around them. Anywhere
+    /// else they are an error of kind `InvalidData`.
+    pub fn write_to<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_to_with(w, &WriterConfig::compact())
+    }
+
+    /// Write the Document to an `io::Write` according to the given
+    /// configuration. See `write_to`.
+    pub fn write_to_with<W: io::Write>(&self, w: &mut W, config: &WriterConfig) -> io::Result<()> {
+        let encoding = match self.encoding {
+            None => Encoding::Utf8,
+            Some(ref name) => match Encoding::from_name(name.as_str()) {
+                Some(encoding) => encoding,
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                  format!("Unsupported encoding: {}", name))),
+            },
+        };
+        let mut out = EncodingWriter::new(w, encoding);
+        try!(out.write_bom());
+        match self.write_with(&mut out, config) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(out.take_error()),
+        }
+    }
+
+}
+
+impl FromStr for Document {
+
+    type Err = BuildError;
+
+    fn from_str(s: &str) -> Result<Document, BuildError> {
+        Document::from_str_with_config(s, None)
+    }
+
 }
 
 impl fmt::Debug for Document {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        try!(self.print_header(f));
+        if self.has_declaration() {
+            try!(self.print_header(f));
+        }
         try!(f.write_str("\n"));
-        write!(f, "{:?}", *self.root.borrow())
+        let doctype_index = cmp::min(self.doctype_index, self.prolog.len());
+        for (i, node) in self.children().iter().enumerate() {
+            if i == doctype_index {
+                if let Some(ref doctype) = self.doctype {
+                    try!(write!(f, "{}\n", doctype));
+                }
+            }
+            try!(write!(f, "{:?}", *node.borrow()));
+        }
+        Ok(())
     }
 
 }
@@ -44,8 +192,7 @@ impl fmt::Debug for Document {
 impl fmt::Display for Document {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        try!(self.print_header(f));
-        write!(f, "{}", *self.root.borrow())
+        self.write_with(f, &WriterConfig::compact())
     }
 
 }
@@ -1,10 +1,13 @@
 use std::cell::RefCell;
-use std::fmt;
+use std::fmt::{self, Write};
 use std::iter::Iterator;
+use std::mem;
 use std::rc::{Rc, Weak};
 use std::slice::Iter;
 
-use dom::{self, Node, RcNode, TextIterator};
+use dom::{CommentIterator, EventIterator, Node, Position, ProcessingInstructionIterator, RcNode, TextIterator};
+use dom::qname::ToQName;
+use dom::writer::{Writer, WriterConfig};
 
 use xml::attribute::OwnedAttribute;
 use xml::name::OwnedName;
@@ -22,6 +25,10 @@ pub struct Element {
     pub namespace: Namespace,
     /// children nodes
     children: Vec<RcNode>,
+    /// position where the start tag starts in the source document
+    pub start: Option<Position>,
+    /// position where the end tag starts in the source document
+    pub end: Option<Position>,
 }
 
 impl Element {
@@ -33,6 +40,8 @@ impl Element {
             attributes: attributes,
             namespace: namespace,
             children: Vec::new(),
+            start: None,
+            end: None,
         }
     }
 
@@ -43,6 +52,8 @@ impl Element {
             attributes: attributes,
             namespace: namespace,
             children: Vec::new(),
+            start: None,
+            end: None,
         }
     }
 
@@ -62,20 +73,82 @@ impl Element {
         self.children.push(node);
     }
 
+    /// Merge adjacent text nodes and remove empty ones,
+    /// in this element and all of its descendants.
+    pub fn normalize(&mut self) {
+        let children = mem::replace(&mut self.children, Vec::new());
+        for child in children.into_iter() {
+            let merged = match *child.borrow() {
+                Node::Element(ref elem) => {
+                    elem.borrow_mut().normalize();
+                    false
+                }
+                Node::Text(ref text) => {
+                    let text = text.borrow();
+                    if text.content.is_empty() {
+                        continue;
+                    }
+                    match self.children.last() {
+                        Some(last) => match *last.borrow() {
+                            Node::Text(ref prev) => {
+                                prev.borrow_mut().append(text.content.as_str(), text.cdata);
+                                true
+                            }
+                            _ => false,
+                        },
+                        None => false,
+                    }
+                }
+                _ => false,
+            };
+            if !merged {
+                self.children.push(child);
+            }
+        }
+    }
+
     /// Return the number of child nodes.
     pub fn len(&self) -> usize {
         self.children.len()
     }
 
     /// Find children by name.
-    /// Currently ignores namespaces.
-    // TODO namespace
-    pub fn find(&self, name: &str) -> Vec<RcElement> {
+    ///
+    /// The name is a `QName`, a `(namespace URI, local name)` pair, or a
+    /// string resolved against the namespace bindings of this Element,
+    /// see `QName::resolve`. Unprefixed names are in the default namespace,
+    /// use `find_local` to ignore namespaces.
+    pub fn find<N: ToQName>(&self, name: N) -> Vec<RcElement> {
+        let qname = match name.to_qname(&self.namespace) {
+            Some(qname) => qname,
+            None => return Vec::new(),
+        };
+        self.iter_elements().filter(|elem| {
+            qname.matches(&elem.borrow().name)
+        }).collect()
+    }
+
+    /// Find children by local name, ignoring namespaces.
+    pub fn find_local(&self, local_name: &str) -> Vec<RcElement> {
         self.iter_elements().filter(|elem| {
-            elem.borrow().name.borrow().local_name == name
+            elem.borrow().name.local_name == local_name
         }).collect()
     }
 
+    /// Get the value of an attribute by name, see `find`.
+    /// Unlike elements, unprefixed attribute names are in no namespace.
+    pub fn get_attribute<N: ToQName>(&self, name: N) -> Option<&str> {
+        let mut scope = self.namespace.clone();
+        scope.0.remove("");
+        let qname = match name.to_qname(&scope) {
+            Some(qname) => qname,
+            None => return None,
+        };
+        self.attributes.iter()
+            .find(|attr| qname.matches(&attr.name))
+            .map(|attr| attr.value.as_str())
+    }
+
     /// Get the text nodes of this Element concatenated.
     pub fn text(&self) -> String {
         let mut buf = String::new();
@@ -100,18 +173,36 @@ impl Element {
         TextIterator::new(Box::new(self.iter()))
     }
 
+    /// Create an iterator that only yields Node::Comment node types.
+    pub fn iter_comments(&self) -> CommentIterator {
+        CommentIterator::new(Box::new(self.iter()))
+    }
+
+    /// Create an iterator that only yields Node::ProcessingInstruction node types.
+    pub fn iter_processing_instructions(&self) -> ProcessingInstructionIterator {
+        ProcessingInstructionIterator::new(Box::new(self.iter()))
+    }
+
+    /// Create an iterator over the events describing the Element and its descendants.
+    pub fn events(&self) -> EventIterator {
+        EventIterator::new_element(self)
+    }
+
     /// Format the Element in a pretty way.
     pub fn format_pretty<W: fmt::Write>(&self, w: &mut W, indent: usize, inc: usize) -> fmt::Result {
-        let name = self.name.borrow().local_name; // TODO namespace
-        let padding = dom::util::padding(indent, inc);
-
-        try!(write!(w, "{}<{}>\n", padding, name));
-        for child in self.iter() {
-            try!(child.borrow().format_pretty(w, indent + 1, inc))
-        }
-        try!(write!(w, "{}</{}>\n", padding, name));
+        let config = WriterConfig::new()
+            .indent_spaces(inc)
+            .normalize_empty_elements(false)
+            .inline_mixed_content(false);
+        let mut writer = Writer::new(w, &config);
+        try!(writer.indent(indent));
+        try!(writer.write_element(self, &Namespace::empty(), indent));
+        writer.write_str("\n")
+    }
 
-        Ok(())
+    /// Write the Element according to the given configuration.
+    pub fn write_with<W: fmt::Write>(&self, w: &mut W, config: &WriterConfig) -> fmt::Result {
+        Writer::new(w, config).write_element(self, &Namespace::empty(), 0)
     }
 
 }
@@ -129,13 +220,7 @@ impl fmt::Debug for Element {
 impl fmt::Display for Element {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let name = self.name.borrow().local_name;
-        try!(write!(f, "<{}>", name));
-        for child in self.iter() {
-            try!(child.borrow().fmt(f));
-        }
-        try!(write!(f, "</{}>", name));
-        Ok(())
+        self.write_with(f, &WriterConfig::compact())
     }
 
 }
@@ -156,8 +241,8 @@ impl<'a> Iterator for ElementIterator<'a> {
                 None => return None,
                 Some(node) => {
                     match *node.borrow() {
-                        Node::Text(_) => continue,
                         Node::Element(ref elem) => return Some(elem.clone()),
+                        _ => continue,
                     }
                 }
             }
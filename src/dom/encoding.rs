@@ -0,0 +1,218 @@
+use std::ascii::AsciiExt;
+use std::fmt;
+use std::io;
+
+/// Encodings supported when writing a document to an `io::Write`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Ascii,
+}
+
+impl Encoding {
+
+    /// Get the encoding matching an encoding name, such as the one
+    /// found in the XML declaration. The name is case insensitive.
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name.to_ascii_uppercase().as_str() {
+            "UTF-8" | "UTF8" => Some(Encoding::Utf8),
+            "UTF-16" | "UTF-16BE" | "UTF16" | "UTF16BE" => Some(Encoding::Utf16Be),
+            "UTF-16LE" | "UTF16LE" => Some(Encoding::Utf16Le),
+            "ISO-8859-1" | "ISO8859-1" | "ISO_8859-1" | "LATIN1" | "LATIN-1" | "L1" => Some(Encoding::Latin1),
+            "US-ASCII" | "ASCII" => Some(Encoding::Ascii),
+            _ => None,
+        }
+    }
+
+    /// Get the byte order mark written at the start of the output.
+    pub fn bom(&self) -> &'static [u8] {
+        match *self {
+            Encoding::Utf16Le => &[0xFF, 0xFE],
+            Encoding::Utf16Be => &[0xFE, 0xFF],
+            _ => &[],
+        }
+    }
+
+}
+
+/// Adapts an `io::Write` to `fmt::Write`, transcoding everything written
+/// to the given encoding as it goes.
+///
+/// Characters which cannot be represented in a single byte encoding are
+/// written as character references in text and attribute values. CDATA
+/// sections are closed around the reference. References are not
+/// recognized anywhere else, so such characters in names, comments,
+/// processing instructions or the document type declaration are an error
+/// of kind `InvalidData`. To know where it is, the writer follows the
+/// markup it is given, which must be well-formed.
+pub struct EncodingWriter<'a, W: io::Write + 'a> {
+    inner: &'a mut W,
+    encoding: Encoding,
+    error: Option<io::Error>,
+    context: Context,
+    // the last two characters written
+    last: [char; 2],
+}
+
+/// Where the next character written is in the markup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Context {
+    Text,
+    // after `<`
+    Open,
+    // after `<!`
+    Bang,
+    // in a start or end tag, outside of attribute values
+    Tag,
+    // in an attribute value delimited by the given quote
+    Value(char),
+    CData,
+    Comment,
+    ProcessingInstruction,
+    // nesting of the internal subset, and the quote of the current literal
+    Doctype(usize, Option<char>),
+}
+
+impl Context {
+
+    fn next(self, c: char, last: [char; 2]) -> Context {
+        match (self, c) {
+            (Context::Text, '<') => Context::Open,
+            (Context::Open, '!') => Context::Bang,
+            (Context::Open, '?') => Context::ProcessingInstruction,
+            (Context::Open, _) => Context::Tag,
+            (Context::Bang, '-') => Context::Comment,
+            (Context::Bang, '[') => Context::CData,
+            (Context::Bang, _) => Context::Doctype(0, None),
+            (Context::Tag, '"') | (Context::Tag, '\'') => Context::Value(c),
+            (Context::Tag, '>') => Context::Text,
+            (Context::Value(quote), _) if c == quote => Context::Tag,
+            (Context::CData, '>') if last == [']', ']'] => Context::Text,
+            (Context::Comment, '>') if last == ['-', '-'] => Context::Text,
+            (Context::ProcessingInstruction, '>') if last[1] == '?' => Context::Text,
+            (Context::Doctype(depth, Some(quote)), _) if c == quote => Context::Doctype(depth, None),
+            (Context::Doctype(depth, None), '"') | (Context::Doctype(depth, None), '\'') => {
+                Context::Doctype(depth, Some(c))
+            }
+            (Context::Doctype(depth, None), '[') => Context::Doctype(depth + 1, None),
+            (Context::Doctype(depth, None), ']') if depth > 0 => Context::Doctype(depth - 1, None),
+            (Context::Doctype(0, None), '>') => Context::Text,
+            (context, _) => context,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match *self {
+            Context::CData | Context::Text | Context::Value(_) => "text",
+            Context::Open | Context::Tag => "a name",
+            Context::Bang | Context::Doctype(..) => "the document type declaration",
+            Context::Comment => "a comment",
+            Context::ProcessingInstruction => "a processing instruction",
+        }
+    }
+
+}
+
+impl<'a, W: io::Write> EncodingWriter<'a, W> {
+
+    pub fn new(inner: &'a mut W, encoding: Encoding) -> EncodingWriter<'a, W> {
+        EncodingWriter {
+            inner: inner,
+            encoding: encoding,
+            error: None,
+            context: Context::Text,
+            last: ['\0', '\0'],
+        }
+    }
+
+    /// Write the byte order mark of the encoding, if it has one.
+    pub fn write_bom(&mut self) -> io::Result<()> {
+        self.inner.write_all(self.encoding.bom())
+    }
+
+    /// Get the `io::Error` which caused a `fmt::Error`.
+    pub fn take_error(&mut self) -> io::Error {
+        match self.error.take() {
+            Some(err) => err,
+            None => io::Error::new(io::ErrorKind::Other, "formatter error"),
+        }
+    }
+
+    fn encode(&mut self, s: &str) -> io::Result<Vec<u8>> {
+        Ok(match self.encoding {
+            Encoding::Utf8 => s.as_bytes().to_vec(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let mut buf = Vec::with_capacity(s.len() * 2);
+                for c in s.chars() {
+                    let code = c as u32;
+                    if code < 0x10000 {
+                        self.push_unit(&mut buf, code as u16);
+                    } else {
+                        let code = code - 0x10000;
+                        self.push_unit(&mut buf, (0xD800 + (code >> 10)) as u16);
+                        self.push_unit(&mut buf, (0xDC00 + (code & 0x3FF)) as u16);
+                    }
+                }
+                buf
+            }
+            Encoding::Latin1 | Encoding::Ascii => {
+                let max = if self.encoding == Encoding::Latin1 { 0xFF } else { 0x7F };
+                let mut buf = Vec::with_capacity(s.len());
+                for c in s.chars() {
+                    let code = c as u32;
+                    if code <= max {
+                        buf.push(code as u8);
+                    } else {
+                        match self.context {
+                            Context::Text | Context::Value(_) => {
+                                buf.extend(format!("&#x{:X};", code).bytes());
+                            }
+                            Context::CData => buf.extend(format!("]]>&#x{:X};<![CDATA[", code).bytes()),
+                            context => {
+                                let msg = format!("{:?} cannot be written in {} as {:?}",
+                                                  c, context.describe(), self.encoding);
+                                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                            }
+                        }
+                    }
+                    self.context = self.context.next(c, self.last);
+                    self.last = [self.last[1], c];
+                }
+                buf
+            }
+        })
+    }
+
+    fn push_unit(&self, buf: &mut Vec<u8>, unit: u16) {
+        let (hi, lo) = ((unit >> 8) as u8, unit as u8);
+        if self.encoding == Encoding::Utf16Le {
+            buf.push(lo);
+            buf.push(hi);
+        } else {
+            buf.push(hi);
+            buf.push(lo);
+        }
+    }
+
+}
+
+impl<'a, W: io::Write> fmt::Write for EncodingWriter<'a, W> {
+
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let result = match self.encode(s) {
+            Ok(bytes) => self.inner.write_all(&bytes),
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                self.error = Some(err);
+                Err(fmt::Error)
+            }
+        }
+    }
+
+}
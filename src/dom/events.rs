@@ -0,0 +1,129 @@
+use dom::document::Document;
+use dom::element::Element;
+use dom::node::{Node, RcNode};
+
+use xml::common::XmlVersion;
+use xml::reader::events::XmlEvent;
+
+/// An iterator over the events describing a Document or an Element,
+/// the inverse of the builder.
+///
+/// Text made of whitespace only is yielded as `Whitespace`, CDATA
+/// sections as `CData`. The document type declaration has no event.
+/// The iterator holds references to the nodes, not borrows, nodes
+/// which are not yet visited can be modified during the iteration.
+pub struct EventIterator {
+    // event to yield before walking the nodes
+    start: Option<XmlEvent>,
+    // the nodes being walked, from the outermost
+    stack: Vec<Frame>,
+}
+
+struct Frame {
+    nodes: Vec<RcNode>,
+    next: usize,
+    // event to yield once all the nodes are walked
+    end: XmlEvent,
+}
+
+impl EventIterator {
+
+    /// Create an iterator over the events of a Document, from
+    /// `StartDocument` to `EndDocument`.
+    pub fn new_document(doc: &Document) -> EventIterator {
+        EventIterator {
+            start: Some(XmlEvent::StartDocument {
+                version: doc.version.unwrap_or(XmlVersion::Version10),
+                encoding: doc.encoding.clone().unwrap_or("UTF-8".to_string()),
+                standalone: doc.standalone,
+            }),
+            stack: vec![Frame {
+                nodes: doc.children(),
+                next: 0,
+                end: XmlEvent::EndDocument,
+            }],
+        }
+    }
+
+    /// Create an iterator over the events of an Element,
+    /// from its `StartElement` to its `EndElement`.
+    pub fn new_element(elem: &Element) -> EventIterator {
+        EventIterator {
+            start: Some(start_element(elem)),
+            stack: vec![element_frame(elem)],
+        }
+    }
+
+}
+
+impl Iterator for EventIterator {
+
+    type Item = XmlEvent;
+
+    fn next(&mut self) -> Option<XmlEvent> {
+        if let Some(event) = self.start.take() {
+            return Some(event);
+        }
+
+        let node = match self.stack.last_mut() {
+            None => return None,
+            Some(frame) => {
+                if frame.next < frame.nodes.len() {
+                    frame.next += 1;
+                    Some(frame.nodes[frame.next - 1].clone())
+                } else {
+                    None
+                }
+            }
+        };
+
+        let node = match node {
+            Some(node) => node,
+            // move out of the element or document
+            None => return self.stack.pop().map(|frame| frame.end),
+        };
+        let event = match *node.borrow() {
+            Node::Element(ref elem) => {
+                let elem = elem.borrow();
+                self.stack.push(element_frame(&elem));
+                start_element(&elem)
+            }
+            Node::Text(ref text) => {
+                let text = text.borrow();
+                if text.cdata {
+                    XmlEvent::CData(text.content.clone())
+                } else if text.content.trim().is_empty() {
+                    XmlEvent::Whitespace(text.content.clone())
+                } else {
+                    XmlEvent::Characters(text.content.clone())
+                }
+            }
+            Node::Comment(ref comment) => XmlEvent::Comment(comment.borrow().content.clone()),
+            Node::ProcessingInstruction(ref pi) => {
+                let pi = pi.borrow();
+                XmlEvent::ProcessingInstruction {
+                    name: pi.target.clone(),
+                    data: pi.data.clone(),
+                }
+            }
+        };
+        Some(event)
+    }
+
+}
+
+fn start_element(elem: &Element) -> XmlEvent {
+    XmlEvent::StartElement {
+        name: elem.name.clone(),
+        attributes: elem.attributes.clone(),
+        namespace: elem.namespace.clone(),
+    }
+}
+
+fn element_frame(elem: &Element) -> Frame {
+    Frame {
+        nodes: elem.iter().cloned().collect(),
+        next: 0,
+        end: XmlEvent::EndElement { name: elem.name.clone() },
+    }
+}
@@ -1,25 +1,47 @@
+pub use self::comment::{Comment, CommentIterator, RcComment, rc_comment_new};
+pub use self::doctype::Doctype;
 pub use self::document::Document;
 pub use self::element::{Element, ElementIterator, RcElement, WeakElement, rc_element_new};
+pub use self::events::EventIterator;
 pub use self::node::{Node, RcNode, rc_node_new};
+pub use self::position::Position;
+pub use self::qname::{QName, ToQName};
+pub use self::pi::{ProcessingInstruction, ProcessingInstructionIterator, RcProcessingInstruction, rc_processing_instruction_new};
 pub use self::text::{Text, TextIterator, RcText, rc_text_new};
+pub use self::util::escape_attribute;
+pub use self::writer::{WriterConfig, XmlDeclaration};
 
+mod comment;
+mod doctype;
 mod document;
 mod element;
+mod encoding;
+mod events;
+mod namespace;
 mod node;
+mod pi;
+mod position;
+mod qname;
 mod text;
 mod util;
+mod writer;
 
 #[cfg(test)]
 mod tests {
-    use builder::build;
-    use super::{Document, RcElement, RcText};
+    use std::io;
+
+    use builder::{build, build_from_events};
+    use super::{Document, Node, QName, Text, rc_node_new, rc_text_new, RcComment, RcElement, RcProcessingInstruction, RcText, WriterConfig, XmlDeclaration};
 
     use xml::EventReader;
+    use xml::reader::config::ParserConfig;
+    use xml::attribute::OwnedAttribute;
+    use xml::name::OwnedName;
     use xml::common::XmlVersion;
+    use xml::reader::events::XmlEvent;
 
     fn xml_to_doc(text: &str) -> Document {
-        let mut reader = EventReader::new(text.as_bytes());
-        let res = build(&mut reader);
+        let res = Document::from_str_with_config(text, None);
         match res {
             Ok(doc) => doc,
             Err(err) => panic!("Error: {}", err),
@@ -98,4 +120,299 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_display_attributes() {
+        let xml = "<root a=\"1 &amp; 2\" b=\"&lt;&quot;x&quot;&#10;\"><item c=\"d\"></item></root>";
+        let doc = xml_to_doc(xml);
+
+        assert_eq!(format!("{}", *doc.root.borrow()), xml);
+    }
+
+    #[test]
+    fn test_display_text() {
+        let xml = "<root>a &lt; b &amp;&amp; c &gt; d<![CDATA[<raw> & ]]]]><![CDATA[>]]></root>";
+        let doc = xml_to_doc(xml);
+        let root = doc.root.borrow();
+
+        assert_eq!(root.text(), "a < b && c > d<raw> & ]]>");
+        assert_eq!(format!("{}", *root), xml);
+
+        let doc = xml_to_doc("<root>a</root>");
+        let root = doc.root.borrow();
+        root.iter_text().next().unwrap().borrow_mut().content = "a\r\nb".to_string();
+        assert_eq!(format!("{}", *root), "<root>a&#13;\nb</root>");
+    }
+
+    #[test]
+    fn test_display_namespaces() {
+        let xml = "<a:root xmlns=\"urn:d\" xmlns:a=\"urn:a\"><item a:x=\"1\"><a:item xmlns=\"\"></a:item></item></a:root>";
+        let doc = xml_to_doc(xml);
+
+        assert_eq!(format!("{}", *doc.root.borrow()), xml);
+    }
+
+    #[test]
+    fn test_display_invented_prefix() {
+        let xml = "<root></root>";
+        let doc = xml_to_doc(xml);
+        let mut root = doc.root.borrow_mut();
+        root.name.namespace = Some("urn:x".to_string());
+        root.attributes.push(OwnedAttribute {
+            name: OwnedName {
+                local_name: "attr".to_string(),
+                namespace: Some("urn:y".to_string()),
+                prefix: None,
+            },
+            value: "v".to_string(),
+        });
+
+        assert_eq!(format!("{}", *root), "<ns0:root xmlns:ns0=\"urn:x\" xmlns:ns1=\"urn:y\" ns1:attr=\"v\"></ns0:root>");
+    }
+
+    #[test]
+    fn test_display_conflicting_prefix() {
+        let doc = xml_to_doc("<a:x xmlns:a=\"urn:a\"></a:x>");
+        let mut root = doc.root.borrow_mut();
+        root.name.namespace = Some("urn:x".to_string());
+        assert_eq!(format!("{}", *root), "<ns0:x xmlns:a=\"urn:a\" xmlns:ns0=\"urn:x\"></ns0:x>");
+
+        root.name.namespace = Some("urn:a".to_string());
+        root.attributes.push(OwnedAttribute {
+            name: OwnedName {
+                local_name: "attr".to_string(),
+                namespace: Some("urn:y".to_string()),
+                prefix: Some("a".to_string()),
+            },
+            value: "v".to_string(),
+        });
+        assert_eq!(format!("{}", *root), "<a:x xmlns:a=\"urn:a\" xmlns:ns0=\"urn:y\" ns0:attr=\"v\"></a:x>");
+    }
+
+    #[test]
+    fn test_writer_config() {
+        let xml = "<root>\n<a x=\"1\" y=\"2\"><b></b></a>\n<c>some <i>mixed</i> text</c>\n</root>";
+        let doc = xml_to_doc(xml);
+        let root = doc.root.borrow();
+
+        let mut buf = String::new();
+        root.write_with(&mut buf, &WriterConfig::new()).unwrap();
+        assert_eq!(buf, "<root>\n  <a x=\"1\" y=\"2\">\n    <b/>\n  </a>\n  <c>some <i>mixed</i> text</c>\n</root>");
+
+        let config = WriterConfig::new()
+            .indent_string("\t")
+            .line_separator("\r\n")
+            .normalize_empty_elements(false)
+            .max_line_width(Some(10));
+        let mut buf = String::new();
+        root.write_with(&mut buf, &config).unwrap();
+        assert_eq!(buf, "<root>\r\n\t<a\r\n\t\tx=\"1\"\r\n\t\ty=\"2\">\r\n\t\t<b></b>\r\n\t</a>\r\n\t<c>some <i>mixed</i> text</c>\r\n</root>");
+    }
+
+    #[test]
+    fn test_write_to_encoding() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?><r a=\"\u{e9}\u{3b1}\">\u{e9}\u{1f600}</r>";
+        let mut doc = xml_to_doc(xml);
+
+        let mut buf = Vec::new();
+        doc.write_to(&mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), format!("{}", doc));
+
+        doc.encoding = Some("ISO-8859-1".to_string());
+        let mut buf = Vec::new();
+        doc.write_to(&mut buf).unwrap();
+        assert!(buf.ends_with(b"<r a=\"\xe9&#x3B1;\">\xe9&#x1F600;</r>"));
+
+        let latin1 = |doc: &mut Document| {
+            doc.encoding = Some("ISO-8859-1".to_string());
+            let mut buf = Vec::new();
+            doc.write_to(&mut buf).map(|_| buf)
+        };
+        let buf = latin1(&mut xml_to_doc("<r><![CDATA[<\u{3b1}>]]></r>")).unwrap();
+        assert!(buf.ends_with(b"<r><![CDATA[<]]>&#x3B1;<![CDATA[>]]></r>"));
+
+        let config = ParserConfig::new().ignore_comments(false);
+        let mut commented = Document::from_str_with_config("<r><!-- \u{3b1} --></r>", Some(config)).unwrap();
+        assert_eq!(latin1(&mut commented).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        let err = latin1(&mut xml_to_doc("<r \u{3b1}=\"\u{3b1}\"/>")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        doc.encoding = Some("UTF-16LE".to_string());
+        let mut buf = Vec::new();
+        doc.write_to(&mut buf).unwrap();
+        assert!(buf.starts_with(&[0xFF, 0xFE, b'<', 0]));
+        assert!(buf.ends_with(&[0xE9, 0, 0x3D, 0xD8, 0x00, 0xDE, b'<', 0, b'/', 0, b'r', 0, b'>', 0]));
+    }
+
+    #[test]
+    fn test_declaration() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><root></root>";
+        let mut doc = xml_to_doc(xml);
+
+        assert_eq!(doc.standalone, Some(true));
+        assert_eq!(format!("{}", doc), xml);
+
+        let mut buf = String::new();
+        let config = WriterConfig::compact().xml_declaration(XmlDeclaration::Never);
+        doc.write_with(&mut buf, &config).unwrap();
+        assert_eq!(buf, "<root></root>");
+
+        doc.version = None;
+        doc.encoding = None;
+        doc.standalone = None;
+        assert_eq!(format!("{}", doc), "<root></root>");
+
+        let mut buf = String::new();
+        let config = WriterConfig::compact().xml_declaration(XmlDeclaration::Always);
+        doc.write_with(&mut buf, &config).unwrap();
+        assert_eq!(buf, "<?xml version=\"1.0\"?><root></root>");
+
+        // the parser reports a declaration when there is none
+        let doc = xml_to_doc("<root/>");
+        assert!(!doc.has_declaration());
+        assert_eq!(doc.to_string(), "<root></root>");
+        assert!(xml_to_doc("\u{feff}<?xml version='1.0'?><root/>").has_declaration());
+    }
+
+    #[test]
+    fn test_comments() {
+        let xml = "<root><!-- a comment --><item>a</item><!--other--></root>";
+        let config = ParserConfig::new().ignore_comments(false);
+        let mut reader = EventReader::new_with_config(xml.as_bytes(), config);
+        let doc = build(&mut reader).unwrap();
+        let root = doc.root.borrow();
+
+        let v: Vec<RcComment> = root.iter_comments().collect();
+        assert_eq!(v.len(), 2);
+        assert_eq!(v[0].borrow().content, " a comment ");
+        assert!(v[1].borrow().get_parent().is_some());
+        assert_eq!(format!("{}", *root), xml);
+
+        let mut buf = String::new();
+        root.write_with(&mut buf, &WriterConfig::new()).unwrap();
+        assert_eq!(buf, "<root>\n  <!-- a comment -->\n  <item>a</item>\n  <!--other-->\n</root>");
+
+        // the entry points given no configuration keep comments too
+        let parsed = Document::from_str_with_config(xml, None).unwrap();
+        assert_eq!(format!("{}", *parsed.root.borrow()), xml);
+        let parsed: Document = xml.parse().unwrap();
+        assert_eq!(parsed.root.borrow().iter_comments().count(), 2);
+    }
+
+    #[test]
+    fn test_processing_instructions() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?><?xml-stylesheet href=\"a.xsl\" type=\"text/xsl\"?><root><?php echo 1; ?><?empty?></root>";
+        let doc = xml_to_doc(xml);
+
+        assert_eq!(doc.prolog.len(), 1);
+        match *doc.prolog[0].borrow() {
+            Node::ProcessingInstruction(ref pi) => {
+                let pi = pi.borrow();
+                assert_eq!(pi.target, "xml-stylesheet");
+                assert_eq!(pi.data, Some("href=\"a.xsl\" type=\"text/xsl\"".to_string()));
+                assert!(pi.get_parent().is_none());
+            }
+            _ => panic!("Expected a processing instruction."),
+        }
+
+        let root = doc.root.borrow();
+        let v: Vec<RcProcessingInstruction> = root.iter_processing_instructions().collect();
+        assert_eq!(v.len(), 2);
+        assert_eq!(v[0].borrow().target, "php");
+        assert_eq!(v[1].borrow().data, None);
+        assert_eq!(format!("{}", doc), xml);
+    }
+
+    #[test]
+    fn test_prolog_epilog() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?><!-- License header --><?pi?><root><item/></root><!-- trailer -->";
+        let config = ParserConfig::new().ignore_comments(false);
+        let mut reader = EventReader::new_with_config(xml.as_bytes(), config);
+        let doc = build(&mut reader).unwrap();
+
+        assert_eq!(doc.prolog.len(), 2);
+        assert_eq!(doc.epilog.len(), 1);
+        assert_eq!(doc.children().len(), 4);
+        assert!(doc.root.borrow().iter_comments().next().is_none());
+
+        let mut buf = String::new();
+        doc.write_with(&mut buf, &WriterConfig::new()).unwrap();
+        assert_eq!(buf, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!-- License header -->\n<?pi?>\n<root>\n  <item/>\n</root>\n<!-- trailer -->\n");
+    }
+
+    #[test]
+    fn test_normalize() {
+        let xml = "<root>a<item>b</item>c</root>";
+        let doc = xml_to_doc(xml);
+        {
+            let mut root = doc.root.borrow_mut();
+            let weak = doc.root.clone().downgrade();
+            root.add_child(rc_node_new(Node::Text(rc_text_new(Text::new(weak.clone(), "".to_string())))));
+            root.add_child(rc_node_new(Node::Text(rc_text_new(Text::new(weak.clone(), "d".to_string())))));
+            let item = root.find("item")[0].clone();
+            let weak = item.clone().downgrade();
+            item.borrow_mut().add_child(rc_node_new(Node::Text(rc_text_new(Text::new(weak, "e".to_string())))));
+            root.normalize();
+        }
+        let root = doc.root.borrow();
+
+        assert_eq!(root.len(), 3);
+        assert_eq!(root.iter_text().last().unwrap().borrow().content, "cd");
+        assert_eq!(root.find("item")[0].borrow().len(), 1);
+        assert_eq!(format!("{}", *root), "<root>a<item>be</item>cd</root>");
+    }
+
+    #[test]
+    fn test_events() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?><root a=\"1\"><item>aa<![CDATA[<b>]]></item> <?pi data?></root>";
+        let doc = xml_to_doc(xml);
+
+        let events: Vec<XmlEvent> = doc.root.borrow().events().collect();
+        assert_eq!(events.len(), 7);
+        match events[0] {
+            XmlEvent::StartElement { ref name, ref attributes, .. } => {
+                assert_eq!(name.local_name, "root");
+                assert_eq!(attributes.len(), 1);
+            }
+            _ => panic!("Expected a start element."),
+        }
+        match events[3] {
+            XmlEvent::CData(ref content) => assert_eq!(content, "<b>"),
+            _ => panic!("Expected a CDATA section."),
+        }
+        match events[6] {
+            XmlEvent::EndElement { ref name } => assert_eq!(name.local_name, "root"),
+            _ => panic!("Expected an end element."),
+        }
+
+        let copy = build_from_events(doc.events()).unwrap();
+        assert_eq!(copy.to_string(), doc.to_string());
+    }
+
+    #[test]
+    fn test_find_namespaces() {
+        let xml = "<root xmlns=\"urn:d\" xmlns:a=\"urn:a\" xmlns:b=\"urn:b\" a:id=\"1\" id=\"2\">\
+                   <item/><a:item/><b:item/><item xmlns=\"\"/></root>";
+        let doc = xml_to_doc(xml);
+        let root = doc.root.borrow();
+
+        assert_eq!(root.find_local("item").len(), 4);
+        assert_eq!(root.find("item").len(), 1);
+        assert_eq!(root.find(&"item".to_string()).len(), 1);
+        assert_eq!(root.find("{}item".to_string()).len(), 1);
+        assert_eq!(root.find("a:item").len(), 1);
+        assert_eq!(root.find("c:item").len(), 0);
+        assert_eq!(root.find("{urn:b}item").len(), 1);
+        assert_eq!(root.find("{}item").len(), 1);
+        assert_eq!(root.find(("urn:a", "item")).len(), 1);
+        assert_eq!(root.find(QName::new(Some("urn:d"), "item")).len(), 1);
+
+        let qname = QName::resolve("b:item", &root.namespace).unwrap();
+        assert_eq!(qname.to_string(), "{urn:b}item");
+        assert_eq!(root.iter_elements().filter(|elem| qname.matches(&elem.borrow().name)).count(), 1);
+
+        assert_eq!(root.get_attribute("id"), Some("2"));
+        assert_eq!(root.get_attribute("a:id"), Some("1"));
+        assert_eq!(root.get_attribute(("urn:b", "id")), None);
+    }
+
 }
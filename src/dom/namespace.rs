@@ -0,0 +1,170 @@
+use std::fmt;
+
+use dom::{self, Element};
+
+use xml::name::Name;
+use xml::namespace::{Namespace, NS_NO_PREFIX, NS_XMLNS_PREFIX, NS_XML_PREFIX};
+
+/// Describes the start tag of an element as it should be written,
+/// with qualified names and the namespace declarations it introduces.
+pub struct StartTag {
+    /// qualified element name
+    pub name: String,
+    /// namespace declarations, as (prefix, uri) pairs, the empty prefix being the default namespace
+    pub declarations: Vec<(String, String)>,
+    /// qualified attribute names and raw values
+    pub attributes: Vec<(String, String)>,
+    /// the namespace bindings in scope for the children of the element
+    pub scope: Namespace,
+}
+
+impl StartTag {
+
+    /// Resolve the start tag of `elem` given the bindings already in scope
+    /// in the output.
+    ///
+    /// Only the bindings of the element's namespace that are new relative
+    /// to `parent` are declared. Names which have a namespace URI but no
+    /// usable prefix, or whose prefix is bound to another URI on the same
+    /// tag, get one, either an existing prefix bound to the URI or
+    /// an invented one of the form `nsN`.
+    pub fn new(elem: &Element, parent: &Namespace) -> StartTag {
+        let mut tag = StartTag {
+            name: String::new(),
+            declarations: Vec::new(),
+            attributes: Vec::new(),
+            scope: parent.clone(),
+        };
+
+        for (prefix, uri) in elem.namespace.0.iter() {
+            if prefix == NS_XML_PREFIX || prefix == NS_XMLNS_PREFIX {
+                continue;
+            }
+            let current = tag.scope.0.get(prefix).map(|s| s.as_str()).unwrap_or("");
+            if current != uri.as_str() {
+                tag.declare(prefix.as_str(), uri.as_str());
+            }
+        }
+
+        tag.name = tag.qualify(elem.name.borrow(), true);
+        for attr in elem.attributes.iter() {
+            let name = tag.qualify(attr.name.borrow(), false);
+            tag.attributes.push((name, attr.value.clone()));
+        }
+
+        tag
+    }
+
+    /// Write the start tag without its closing `>`.
+    /// `separator` is written before each declaration and attribute.
+    pub fn write<W: fmt::Write>(&self, w: &mut W, separator: &str) -> fmt::Result {
+        try!(write!(w, "<{}", self.name));
+        for &(ref prefix, ref uri) in self.declarations.iter() {
+            let uri = dom::util::escape_attribute(uri.as_str());
+            if prefix.is_empty() {
+                try!(write!(w, "{}xmlns=\"{}\"", separator, uri));
+            } else {
+                try!(write!(w, "{}xmlns:{}=\"{}\"", separator, prefix, uri));
+            }
+        }
+        for &(ref name, ref value) in self.attributes.iter() {
+            try!(write!(w, "{}{}=\"{}\"", separator, name, dom::util::escape_attribute(value.as_str())));
+        }
+        Ok(())
+    }
+
+    fn declare(&mut self, prefix: &str, uri: &str) {
+        self.scope.0.insert(prefix.to_string(), uri.to_string());
+        self.declarations.push((prefix.to_string(), uri.to_string()));
+    }
+
+    /// Whether `prefix` is already declared on this tag.
+    fn is_declared(&self, prefix: &str) -> bool {
+        self.declarations.iter().any(|&(ref declared, _)| declared.as_str() == prefix)
+    }
+
+    /// Get the qualified name to write for `name`, declaring its prefix if needed.
+    /// Unprefixed attributes are never in the default namespace,
+    /// hence `use_default`.
+    fn qualify(&mut self, name: Name, use_default: bool) -> String {
+        let uri = name.namespace.unwrap_or("");
+
+        if uri.is_empty() {
+            if use_default && name.prefix.is_none() && self.default_uri() != "" {
+                // the name of the element wins over a default namespace
+                // declared on the same tag
+                self.declarations.retain(|&(ref prefix, _)| !prefix.is_empty());
+                self.declare(NS_NO_PREFIX, "");
+            }
+            return join(name.prefix, name.local_name);
+        }
+
+        let prefix = match name.prefix {
+            Some(prefix) if !prefix.is_empty() => {
+                if prefix == NS_XML_PREFIX || self.scope.0.get(prefix).map(|s| s.as_str()) == Some(uri) {
+                    Some(prefix.to_string())
+                } else if self.is_declared(prefix) {
+                    // the prefix is bound to another URI on this very tag
+                    Some(self.bind_prefix(uri))
+                } else {
+                    self.declare(prefix, uri);
+                    Some(prefix.to_string())
+                }
+            }
+            _ => {
+                if use_default && self.default_uri() == uri {
+                    None
+                } else {
+                    Some(self.bind_prefix(uri))
+                }
+            }
+        };
+
+        join(prefix.as_ref().map(|s| s.as_str()), name.local_name)
+    }
+
+    /// Get a prefix bound to `uri`, declaring a new one if there is none.
+    fn bind_prefix(&mut self, uri: &str) -> String {
+        match self.find_prefix(uri) {
+            Some(prefix) => prefix,
+            None => {
+                let prefix = self.invent_prefix();
+                self.declare(prefix.as_str(), uri);
+                prefix
+            }
+        }
+    }
+
+    fn default_uri(&self) -> &str {
+        self.scope.0.get(NS_NO_PREFIX).map(|s| s.as_str()).unwrap_or("")
+    }
+
+    /// Find a non empty prefix bound to `uri`.
+    fn find_prefix(&self, uri: &str) -> Option<String> {
+        for (prefix, bound) in self.scope.0.iter() {
+            if !prefix.is_empty() && prefix != NS_XMLNS_PREFIX && bound.as_str() == uri {
+                return Some(prefix.clone());
+            }
+        }
+        None
+    }
+
+    fn invent_prefix(&self) -> String {
+        let mut n = 0;
+        loop {
+            let prefix = format!("ns{}", n);
+            if !self.scope.0.contains_key(&prefix) {
+                return prefix;
+            }
+            n += 1;
+        }
+    }
+
+}
+
+fn join(prefix: Option<&str>, local_name: &str) -> String {
+    match prefix {
+        Some(prefix) if !prefix.is_empty() => format!("{}:{}", prefix, local_name),
+        _ => local_name.to_string(),
+    }
+}
@@ -2,14 +2,20 @@ use std::cell::RefCell;
 use std::fmt;
 use std::rc::Rc;
 
+use dom::comment::RcComment;
 use dom::element::RcElement;
+use dom::pi::RcProcessingInstruction;
+use dom::position::Position;
 use dom::text::RcText;
 
 /// Describes a node of the XML tree.
-/// The node can be an element or a text node.
+/// The node can be an element, a text node, a comment
+/// or a processing instruction.
 pub enum Node {
     Element(RcElement),
     Text(RcText),
+    Comment(RcComment),
+    ProcessingInstruction(RcProcessingInstruction),
 }
 
 impl Node {
@@ -21,6 +27,30 @@ impl Node {
         match *self {
             Node::Element(ref elem) => elem.borrow().get_parent(),
             Node::Text(ref text) => text.borrow().get_parent(),
+            Node::Comment(ref comment) => comment.borrow().get_parent(),
+            Node::ProcessingInstruction(ref pi) => pi.borrow().get_parent(),
+        }
+    }
+
+    /// Get the position where the node starts in the source document,
+    /// the start of the start tag for elements. See `Position`.
+    /// Returns `None` if the node was not created by the builder.
+    pub fn start(&self) -> Option<Position> {
+        match *self {
+            Node::Element(ref elem) => elem.borrow().start,
+            Node::Text(ref text) => text.borrow().position,
+            Node::Comment(ref comment) => comment.borrow().position,
+            Node::ProcessingInstruction(ref pi) => pi.borrow().position,
+        }
+    }
+
+    /// Get the position of the end tag for elements, where it starts.
+    /// Other nodes have a single position, where they start.
+    /// Returns `None` if the node was not created by the builder.
+    pub fn end(&self) -> Option<Position> {
+        match *self {
+            Node::Element(ref elem) => elem.borrow().end,
+            _ => self.start(),
         }
     }
 
@@ -29,6 +59,8 @@ impl Node {
         match *self {
             Node::Element(ref elem) => elem.borrow().format_pretty(w, indent, inc),
             Node::Text(ref text) => text.borrow().format_pretty(w, indent, inc),
+            Node::Comment(ref comment) => comment.borrow().format_pretty(w, indent, inc),
+            Node::ProcessingInstruction(ref pi) => pi.borrow().format_pretty(w, indent, inc),
         }
     }
 
@@ -40,6 +72,8 @@ impl fmt::Debug for Node {
         match *self {
             Node::Element(ref elem) => elem.borrow().fmt(f),
             Node::Text(ref elem) => elem.borrow().fmt(f),
+            Node::Comment(ref elem) => elem.borrow().fmt(f),
+            Node::ProcessingInstruction(ref elem) => elem.borrow().fmt(f),
         }
     }
 
@@ -51,6 +85,8 @@ impl fmt::Display for Node {
         match *self {
             Node::Element(ref elem) => elem.borrow().fmt(f),
             Node::Text(ref elem) => elem.borrow().fmt(f),
+            Node::Comment(ref elem) => elem.borrow().fmt(f),
+            Node::ProcessingInstruction(ref elem) => elem.borrow().fmt(f),
         }
     }
 
@@ -0,0 +1,130 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::iter::Iterator;
+use std::rc::Rc;
+use std::slice::Iter;
+
+use dom::{self, Node, Position, RcNode, RcElement, WeakElement};
+
+/// Describes a processing instruction node of the DOM tree.
+pub struct ProcessingInstruction {
+    /// parent element
+    pub parent: Option<WeakElement>,
+    /// the application the instruction is directed to
+    pub target: String,
+    /// the instruction's content, if any
+    pub data: Option<String>,
+    /// position where it starts in the source document
+    pub position: Option<Position>,
+}
+
+impl ProcessingInstruction {
+
+    pub fn new(parent: WeakElement, target: String, data: Option<String>) -> ProcessingInstruction {
+        ProcessingInstruction {
+            parent: Some(parent),
+            target: target,
+            data: data,
+            position: None,
+        }
+    }
+
+    pub fn new_root(target: String, data: Option<String>) -> ProcessingInstruction {
+        ProcessingInstruction {
+            parent: None,
+            target: target,
+            data: data,
+            position: None,
+        }
+    }
+
+    /// Try to get the parent element.
+    /// Returns `None` if the parent is `None`
+    /// or if the upgrade from `Weak` to `Rc` fails.
+    pub fn get_parent(&self) -> Option<RcElement> {
+        match self.parent {
+            None => None,
+            Some(ref parent) => {
+                parent.clone().upgrade()
+            }
+        }
+    }
+
+    /// Get the processing instruction as it should be written in a document.
+    pub fn serialize(&self) -> String {
+        match self.data {
+            None => format!("<?{}?>", self.target),
+            Some(ref data) => format!("<?{} {}?>", self.target, data),
+        }
+    }
+
+    /// Format the ProcessingInstruction in a pretty way.
+    pub fn format_pretty<W: fmt::Write>(&self, w: &mut W, indent: usize, inc: usize) -> fmt::Result {
+        let padding = dom::util::padding(indent, inc);
+        try!(write!(w, "{}{}\n", padding, self.serialize()));
+        Ok(())
+    }
+
+}
+
+impl fmt::Debug for ProcessingInstruction {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut buf = String::new();
+        try!(self.format_pretty(&mut buf, 0, 2));
+        f.write_str(buf.as_str())
+    }
+
+}
+
+impl fmt::Display for ProcessingInstruction {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.serialize())
+    }
+
+}
+
+/// Iterator for processing instruction nodes.
+pub struct ProcessingInstructionIterator<'a> {
+    source: Box<Iter<'a, RcNode>>,
+}
+
+impl<'a> ProcessingInstructionIterator<'a> {
+
+    pub fn new<'b>(source: Box<Iter<'b, RcNode>>) -> ProcessingInstructionIterator<'b> {
+        ProcessingInstructionIterator {
+            source: source,
+        }
+    }
+
+}
+
+impl<'a> Iterator for ProcessingInstructionIterator<'a> {
+
+    type Item = RcProcessingInstruction;
+
+    fn next(&mut self) -> Option<RcProcessingInstruction> {
+        loop {
+            let it = self.source.next();
+            match it {
+                None => return None,
+                Some(node) => {
+                    match *node.borrow() {
+                        Node::ProcessingInstruction(ref pi) => return Some(pi.clone()),
+                        _ => continue,
+                    }
+                }
+            }
+        }
+    }
+
+}
+
+/// A processing instruction node with shared ownership.
+pub type RcProcessingInstruction = Rc<RefCell<ProcessingInstruction>>;
+
+/// Handy constructor for RcProcessingInstruction.
+pub fn rc_processing_instruction_new(pi: ProcessingInstruction) -> RcProcessingInstruction {
+    Rc::new(RefCell::new(pi))
+}
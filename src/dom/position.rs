@@ -0,0 +1,49 @@
+use std::fmt;
+
+use xml::common::HasPosition;
+
+/// Describes a location in the source document.
+/// Lines and columns start at 1.
+///
+/// The builder gives nodes the position of the parser when it starts
+/// reading them. This is where their markup starts, unless the parser
+/// skipped something before it without reporting it, such as the document
+/// type declaration or a comment with `ignore_comments`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    /// line number
+    pub line: usize,
+    /// column number
+    pub column: usize,
+}
+
+impl Position {
+
+    pub fn new(line: usize, column: usize) -> Position {
+        Position {
+            line: line,
+            column: column,
+        }
+    }
+
+}
+
+impl fmt::Display for Position {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+
+}
+
+impl HasPosition for Position {
+
+    fn row(&self) -> usize {
+        self.line.saturating_sub(1)
+    }
+
+    fn col(&self) -> usize {
+        self.column.saturating_sub(1)
+    }
+
+}
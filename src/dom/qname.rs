@@ -0,0 +1,112 @@
+use std::fmt;
+
+use xml::name::OwnedName;
+use xml::namespace::{Namespace, NS_NO_PREFIX, NS_XML_PREFIX, NS_XML_URI};
+
+/// A namespace qualified name, as used to look up elements and attributes.
+/// Two names are the same if they have the same namespace URI and local
+/// name, whatever their prefixes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct QName {
+    /// namespace URI, `None` for names in no namespace
+    pub namespace: Option<String>,
+    /// local name, without the prefix
+    pub local_name: String,
+}
+
+impl QName {
+
+    pub fn new(namespace: Option<&str>, local_name: &str) -> QName {
+        QName {
+            namespace: namespace.and_then(non_empty).map(|s| s.to_string()),
+            local_name: local_name.to_string(),
+        }
+    }
+
+    /// Resolve a name against namespace bindings. The name is either in
+    /// Clark notation, `{uri}local`, or a name with an optional prefix.
+    /// Unprefixed names are in the default namespace, if there is one.
+    /// Returns `None` if the prefix is not bound or the name is malformed.
+    pub fn resolve(name: &str, namespace: &Namespace) -> Option<QName> {
+        if name.starts_with('{') {
+            return name.find('}').map(|end| QName::new(Some(&name[1..end]), &name[end + 1..]));
+        }
+        match name.find(':') {
+            None => {
+                let uri = namespace.0.get(NS_NO_PREFIX).map(|s| s.as_str());
+                Some(QName::new(uri, name))
+            }
+            Some(colon) => {
+                let (prefix, local_name) = (&name[..colon], &name[colon + 1..]);
+                let uri = match namespace.0.get(prefix) {
+                    Some(uri) => uri.as_str(),
+                    None if prefix == NS_XML_PREFIX => NS_XML_URI,
+                    None => return None,
+                };
+                Some(QName::new(Some(uri), local_name))
+            }
+        }
+    }
+
+    /// Check if this is the name of an element or attribute.
+    pub fn matches(&self, name: &OwnedName) -> bool {
+        let namespace = name.namespace.as_ref().map(|s| s.as_str()).and_then(non_empty);
+        self.local_name == name.local_name && self.namespace.as_ref().map(|s| s.as_str()) == namespace
+    }
+
+}
+
+impl fmt::Display for QName {
+
+    /// Write the name in Clark notation.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.namespace {
+            None => write!(f, "{}", self.local_name),
+            Some(ref uri) => write!(f, "{{{}}}{}", uri, self.local_name),
+        }
+    }
+
+}
+
+/// Something which names elements or attributes in lookups,
+/// once resolved against the namespace bindings in scope.
+pub trait ToQName {
+    fn to_qname(&self, namespace: &Namespace) -> Option<QName>;
+}
+
+impl ToQName for QName {
+    fn to_qname(&self, _: &Namespace) -> Option<QName> {
+        Some(self.clone())
+    }
+}
+
+/// See `QName::resolve`.
+impl ToQName for str {
+    fn to_qname(&self, namespace: &Namespace) -> Option<QName> {
+        QName::resolve(self, namespace)
+    }
+}
+
+/// See `QName::resolve`.
+impl ToQName for String {
+    fn to_qname(&self, namespace: &Namespace) -> Option<QName> {
+        QName::resolve(self, namespace)
+    }
+}
+
+impl<'a, N: ToQName + ?Sized> ToQName for &'a N {
+    fn to_qname(&self, namespace: &Namespace) -> Option<QName> {
+        (**self).to_qname(namespace)
+    }
+}
+
+/// A namespace URI and a local name.
+impl<'a, 'b> ToQName for (&'a str, &'b str) {
+    fn to_qname(&self, _: &Namespace) -> Option<QName> {
+        Some(QName::new(Some(self.0), self.1))
+    }
+}
+
+fn non_empty(s: &str) -> Option<&str> {
+    if s.is_empty() { None } else { Some(s) }
+}
@@ -4,35 +4,82 @@ use std::iter::Iterator;
 use std::rc::Rc;
 use std::slice::Iter;
 
-use dom::{self, Node, RcNode, RcElement, WeakElement};
+use dom::{self, Node, Position, RcNode, RcElement, WeakElement};
 
 /// Describes a text node of the DOM tree.
 pub struct Text {
     /// parent element
-    pub parent: WeakElement,
+    pub parent: Option<WeakElement>,
     /// the text node's content
     pub content: String,
+    /// whether the content came from (and is written as) a CDATA section
+    pub cdata: bool,
+    /// position where it starts in the source document
+    pub position: Option<Position>,
 }
 
 impl Text {
 
     pub fn new(parent: WeakElement, content: String) -> Text {
         Text {
-            parent: parent,
+            parent: Some(parent),
             content: content,
+            cdata: false,
+            position: None,
         }
     }
 
-    /// Try and get the parent element.
-    /// Returns `None` if the upgrade from `Weak` to `Rc` fails.
+    pub fn new_cdata(parent: WeakElement, content: String) -> Text {
+        Text {
+            parent: Some(parent),
+            content: content,
+            cdata: true,
+            position: None,
+        }
+    }
+
+    pub fn new_root(content: String) -> Text {
+        Text {
+            parent: None,
+            content: content,
+            cdata: false,
+            position: None,
+        }
+    }
+
+    /// Try to get the parent element.
+    /// Returns `None` if the parent is `None`
+    /// or if the upgrade from `Weak` to `Rc` fails.
     pub fn get_parent(&self) -> Option<RcElement> {
-        self.parent.clone().upgrade()
+        match self.parent {
+            None => None,
+            Some(ref parent) => {
+                parent.clone().upgrade()
+            }
+        }
+    }
+
+    /// Append content to the text node. The text node stays a CDATA
+    /// section only if the appended content comes from one as well.
+    pub fn append(&mut self, content: &str, cdata: bool) {
+        self.content.push_str(content);
+        self.cdata = self.cdata && cdata;
+    }
+
+    /// Get the content as it should be written in a document,
+    /// either escaped or wrapped in a CDATA section.
+    pub fn serialize(&self) -> String {
+        if self.cdata {
+            dom::util::cdata_section(self.content.as_str())
+        } else {
+            dom::util::escape_text(self.content.as_str())
+        }
     }
 
     /// Format the Element in a pretty way.
     pub fn format_pretty<W: fmt::Write>(&self, w: &mut W, indent: usize, inc: usize) -> fmt::Result {
         let padding = dom::util::padding(indent, inc);
-        try!(write!(w, "{}{}\n", padding, self.content));
+        try!(write!(w, "{}{}\n", padding, self.serialize()));
         Ok(())
     }
 
@@ -51,7 +98,7 @@ impl fmt::Debug for Text {
 impl fmt::Display for Text {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.content)
+        write!(f, "{}", self.serialize())
     }
 
 }
@@ -82,8 +129,8 @@ impl<'a> Iterator for TextIterator<'a> {
                 None => return None,
                 Some(node) => {
                     match *node.borrow() {
-                        Node::Element(_) => continue,
                         Node::Text(ref text) => return Some(text.clone()),
+                        _ => continue,
                     }
                 }
             }
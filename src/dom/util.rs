@@ -7,3 +7,71 @@ pub fn padding(indent: usize, inc: usize) -> String {
     }
     buf
 }
+
+/// Escape a string for use inside a double quoted attribute value.
+/// Whitespace characters are escaped as character references so that
+/// they survive attribute value normalization.
+pub fn escape_attribute(value: &str) -> String {
+    let mut buf = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => buf.push_str("&amp;"),
+            '<' => buf.push_str("&lt;"),
+            '"' => buf.push_str("&quot;"),
+            '\t' => buf.push_str("&#9;"),
+            '\n' => buf.push_str("&#10;"),
+            '\r' => buf.push_str("&#13;"),
+            _ => buf.push(c),
+        }
+    }
+    buf
+}
+
+/// Escape a string for use as character data.
+/// Carriage returns are escaped so that they survive line ending
+/// normalization.
+pub fn escape_text(content: &str) -> String {
+    let mut buf = String::with_capacity(content.len());
+    for c in content.chars() {
+        match c {
+            '&' => buf.push_str("&amp;"),
+            '<' => buf.push_str("&lt;"),
+            '>' => buf.push_str("&gt;"),
+            '\r' => buf.push_str("&#13;"),
+            _ => buf.push(c),
+        }
+    }
+    buf
+}
+
+/// Wrap a string in a CDATA section.
+/// Occurrences of `]]>` are split across two sections since
+/// they would otherwise terminate the section early.
+pub fn cdata_section(content: &str) -> String {
+    let mut buf = String::with_capacity(content.len() + 12);
+    buf.push_str("<![CDATA[");
+    buf.push_str(content.replace("]]>", "]]]]><![CDATA[>").as_str());
+    buf.push_str("]]>");
+    buf
+}
+
+/// Wrap a string in comment delimiters.
+/// Comments cannot contain `--` nor end with `-`, so a space is
+/// inserted where needed.
+pub fn comment_section(content: &str) -> String {
+    let mut buf = String::with_capacity(content.len() + 7);
+    buf.push_str("<!--");
+    let mut prev = ' ';
+    for c in content.chars() {
+        if c == '-' && prev == '-' {
+            buf.push(' ');
+        }
+        buf.push(c);
+        prev = c;
+    }
+    if prev == '-' {
+        buf.push(' ');
+    }
+    buf.push_str("-->");
+    buf
+}
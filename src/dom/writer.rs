@@ -0,0 +1,234 @@
+use std::fmt;
+
+use dom::{self, Element, Node, RcNode};
+use dom::namespace::StartTag;
+
+use xml::namespace::Namespace;
+
+/// Options controlling how a `Document` or an `Element` is written.
+///
+/// The setters consume and return the configuration so that they can be chained:
+///
+/// ```ignore
+/// let config = WriterConfig::new().indent_string("\t").normalize_empty_elements(false);
+/// ```
+#[derive(Clone, Debug)]
+pub struct WriterConfig {
+    /// Break lines and indent nested elements.
+    pub perform_indent: bool,
+    /// String written once per level of nesting.
+    pub indent_string: String,
+    /// String used to break lines.
+    pub line_separator: String,
+    /// Write elements without children as `<name/>`.
+    pub normalize_empty_elements: bool,
+    /// Write the children of elements containing text on a single line,
+    /// so that indentation does not alter mixed content.
+    pub inline_mixed_content: bool,
+    /// Put each attribute of a start tag on its own line when the tag
+    /// would be longer than this many characters.
+    pub max_line_width: Option<usize>,
+    /// When to write the XML declaration of documents.
+    pub xml_declaration: XmlDeclaration,
+}
+
+/// When to write the XML declaration of a document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XmlDeclaration {
+    /// Only if the source document had one.
+    Auto,
+    /// Always, the version defaulting to 1.0.
+    Always,
+    /// Never.
+    Never,
+}
+
+impl WriterConfig {
+
+    /// Create a configuration which indents with two spaces.
+    pub fn new() -> WriterConfig {
+        WriterConfig {
+            perform_indent: true,
+            indent_string: "  ".to_string(),
+            line_separator: "\n".to_string(),
+            normalize_empty_elements: true,
+            inline_mixed_content: true,
+            max_line_width: None,
+            xml_declaration: XmlDeclaration::Auto,
+        }
+    }
+
+    /// Create a configuration which writes nodes exactly as they are,
+    /// without adding any whitespace.
+    pub fn compact() -> WriterConfig {
+        WriterConfig::new()
+            .perform_indent(false)
+            .normalize_empty_elements(false)
+    }
+
+    pub fn perform_indent(mut self, value: bool) -> WriterConfig {
+        self.perform_indent = value;
+        self
+    }
+
+    pub fn indent_string<S: Into<String>>(mut self, value: S) -> WriterConfig {
+        self.indent_string = value.into();
+        self
+    }
+
+    /// Indent with `n` spaces per level.
+    pub fn indent_spaces(self, n: usize) -> WriterConfig {
+        self.indent_string(dom::util::padding(1, n))
+    }
+
+    pub fn line_separator<S: Into<String>>(mut self, value: S) -> WriterConfig {
+        self.line_separator = value.into();
+        self
+    }
+
+    pub fn normalize_empty_elements(mut self, value: bool) -> WriterConfig {
+        self.normalize_empty_elements = value;
+        self
+    }
+
+    pub fn inline_mixed_content(mut self, value: bool) -> WriterConfig {
+        self.inline_mixed_content = value;
+        self
+    }
+
+    pub fn max_line_width(mut self, value: Option<usize>) -> WriterConfig {
+        self.max_line_width = value;
+        self
+    }
+
+    pub fn xml_declaration(mut self, value: XmlDeclaration) -> WriterConfig {
+        self.xml_declaration = value;
+        self
+    }
+
+}
+
+/// Writes nodes to a `fmt::Write` according to a `WriterConfig`.
+pub struct Writer<'a, W: fmt::Write + 'a> {
+    w: &'a mut W,
+    config: &'a WriterConfig,
+}
+
+impl<'a, W: fmt::Write> Writer<'a, W> {
+
+    pub fn new(w: &'a mut W, config: &'a WriterConfig) -> Writer<'a, W> {
+        Writer {
+            w: w,
+            config: config,
+        }
+    }
+
+    /// Break the line and indent the next one, if indentation is enabled.
+    pub fn newline(&mut self, depth: usize) -> fmt::Result {
+        if self.config.perform_indent {
+            try!(self.w.write_str(self.config.line_separator.as_str()));
+            try!(self.indent(depth));
+        }
+        Ok(())
+    }
+
+    /// Write the indentation of a line, if indentation is enabled.
+    pub fn indent(&mut self, depth: usize) -> fmt::Result {
+        if self.config.perform_indent {
+            for _ in 0..depth {
+                try!(self.w.write_str(self.config.indent_string.as_str()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write an element and its children.
+    /// `scope` holds the namespace bindings declared by the ancestors and
+    /// `depth` the nesting level, used for indentation.
+    pub fn write_element(&mut self, elem: &Element, scope: &Namespace, depth: usize) -> fmt::Result {
+        self.write_element_in(elem, scope, depth, !self.config.perform_indent)
+    }
+
+    /// Write any node. See `write_element`.
+    pub fn write_node(&mut self, node: &Node, scope: &Namespace, depth: usize) -> fmt::Result {
+        self.write_node_in(node, scope, depth, !self.config.perform_indent)
+    }
+
+    fn write_node_in(&mut self, node: &Node, scope: &Namespace, depth: usize, inline: bool) -> fmt::Result {
+        match *node {
+            Node::Element(ref elem) => self.write_element_in(&*elem.borrow(), scope, depth, inline),
+            Node::Text(ref text) => self.w.write_str(text.borrow().serialize().as_str()),
+            Node::Comment(ref comment) => self.w.write_str(comment.borrow().serialize().as_str()),
+            Node::ProcessingInstruction(ref pi) => self.w.write_str(pi.borrow().serialize().as_str()),
+        }
+    }
+
+    fn write_element_in(&mut self, elem: &Element, scope: &Namespace, depth: usize, inline: bool) -> fmt::Result {
+        let tag = StartTag::new(elem, scope);
+        try!(self.write_start_tag(&tag, depth, inline));
+
+        let inline = inline || (self.config.inline_mixed_content && has_text(elem));
+        let children: Vec<&RcNode> = elem.iter().filter(|child| inline || !is_blank(child)).collect();
+
+        if children.is_empty() && self.config.normalize_empty_elements {
+            return self.w.write_str("/>");
+        }
+        try!(self.w.write_str(">"));
+
+        for child in children.iter() {
+            if !inline {
+                try!(self.newline(depth + 1));
+            }
+            try!(self.write_node_in(&*child.borrow(), &tag.scope, depth + 1, inline));
+        }
+
+        if !inline && !children.is_empty() {
+            try!(self.newline(depth));
+        }
+        write!(self.w, "</{}>", tag.name)
+    }
+
+    fn write_start_tag(&mut self, tag: &StartTag, depth: usize, inline: bool) -> fmt::Result {
+        let wrap = match self.config.max_line_width {
+            Some(width) if !inline && tag.declarations.len() + tag.attributes.len() > 1 => {
+                let mut buf = String::new();
+                try!(tag.write(&mut buf, " "));
+                let indent = depth * self.config.indent_string.chars().count();
+                indent + buf.chars().count() > width
+            }
+            _ => false,
+        };
+
+        if wrap {
+            let mut separator = self.config.line_separator.clone();
+            for _ in 0..depth + 1 {
+                separator.push_str(self.config.indent_string.as_str());
+            }
+            tag.write(self.w, separator.as_str())
+        } else {
+            tag.write(self.w, " ")
+        }
+    }
+
+}
+
+impl<'a, W: fmt::Write> fmt::Write for Writer<'a, W> {
+
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.w.write_str(s)
+    }
+
+}
+
+/// Check if an element directly contains text which is not only whitespace.
+fn has_text(elem: &Element) -> bool {
+    elem.iter_text().any(|text| !text.borrow().content.trim().is_empty())
+}
+
+/// Check if a node is a text node containing only whitespace.
+pub fn is_blank(node: &RcNode) -> bool {
+    match *node.borrow() {
+        Node::Text(ref text) => text.borrow().content.trim().is_empty(),
+        _ => false,
+    }
+}
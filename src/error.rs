@@ -1,20 +1,128 @@
 use std::fmt;
 use std::convert;
+use std::io;
+use std::path::PathBuf;
+
+use dom::{Document, Position};
 
 use xml::common::Error as ParserError;
+use xml::common::HasPosition;
 
 /// An error that occurs trying to build a `Document`.
+///
+/// Errors found in the source document carry the path of the elements
+/// which were open at that point, from the root down, as qualified names.
 #[derive(Debug)]
 pub enum BuildError {
     /// There was no root element. (empty source document).
     UndefinedRoot,
     /// A parser error, see `xml::common::error`.
-    ParserError(ParserError),
+    ParserError(ParserError, Vec<String>),
+    /// An element was found after the root element was closed.
+    MultipleRoots {
+        name: String,
+        position: Option<Position>,
+    },
+    /// Text was found outside of the root element.
+    TextOutsideRoot {
+        position: Option<Position>,
+    },
+    /// The document ended while an element was still open.
+    UnclosedElement {
+        name: String,
+        path: Vec<String>,
+    },
+    /// An end tag was found while no element was open.
+    UnexpectedEndTag {
+        name: String,
+        position: Option<Position>,
+        path: Vec<String>,
+    },
+    /// An end tag does not match the open element.
+    MismatchedTag {
+        expected: String,
+        found: String,
+        position: Option<Position>,
+        path: Vec<String>,
+    },
+    /// A limit set in the `BuildOptions` was exceeded.
+    LimitExceeded {
+        limit: &'static str,
+        max: usize,
+        position: Option<Position>,
+        path: Vec<String>,
+    },
+    /// The source document is not in an encoding the parser can read.
+    Encoding {
+        message: String,
+        position: Option<Position>,
+    },
+    /// An I/O error, while reading the source document.
+    Io(io::Error),
+    /// An error in the source document read from the given path.
+    File(PathBuf, Box<BuildError>),
+}
+
+impl BuildError {
+
+    /// Get the position of the error in the source document, if known.
+    pub fn position(&self) -> Option<Position> {
+        match *self {
+            BuildError::ParserError(ref err, _) => Some(Position::new(err.row() + 1, err.col() + 1)),
+            BuildError::MultipleRoots { position, .. } => position,
+            BuildError::TextOutsideRoot { position } => position,
+            BuildError::UnexpectedEndTag { position, .. } => position,
+            BuildError::MismatchedTag { position, .. } => position,
+            BuildError::LimitExceeded { position, .. } => position,
+            BuildError::Encoding { position, .. } => position,
+            BuildError::File(_, ref err) => err.position(),
+            _ => None,
+        }
+    }
+
+    /// Get the path of the elements which were open when the error occurred.
+    pub fn path(&self) -> &[String] {
+        match *self {
+            BuildError::ParserError(_, ref path) => path,
+            BuildError::UnclosedElement { ref path, .. } => path,
+            BuildError::UnexpectedEndTag { ref path, .. } => path,
+            BuildError::MismatchedTag { ref path, .. } => path,
+            BuildError::LimitExceeded { ref path, .. } => path,
+            BuildError::File(_, ref err) => err.path(),
+            _ => &[],
+        }
+    }
+
+    /// Render the error along with the offending line of the source
+    /// document and a caret pointing at the position of the error.
+    pub fn render(&self, source: &str) -> String {
+        let mut buf = format!("error: {}\n", self);
+        if let Some(pos) = self.position() {
+            if let Some(line) = source.lines().nth(pos.line.saturating_sub(1)) {
+                let number = pos.line.to_string();
+                let gutter: String = number.chars().map(|_| ' ').collect();
+                let caret: String = line.chars().take(pos.column.saturating_sub(1))
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                buf.push_str(&format!("{} |\n", gutter));
+                buf.push_str(&format!("{} | {}\n", number, line));
+                buf.push_str(&format!("{} | {}^\n", gutter, caret));
+            }
+        }
+        buf
+    }
+
 }
 
 impl convert::From<ParserError> for BuildError {
     fn from(err: ParserError) -> Self {
-        BuildError::ParserError(err)
+        BuildError::ParserError(err, Vec::new())
+    }
+}
+
+impl convert::From<io::Error> for BuildError {
+    fn from(err: io::Error) -> Self {
+        BuildError::Io(err)
     }
 }
 
@@ -22,9 +130,57 @@ impl fmt::Display for BuildError {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            // these already tell where the error is, if they can
+            BuildError::ParserError(..) | BuildError::Io(_) | BuildError::File(..) => {}
+            _ => if let Some(position) = self.position() {
+                try!(write!(f, "{}: ", position));
+            },
+        }
+        try!(match *self {
             BuildError::UndefinedRoot => write!(f, "Undefined root element."),
-            BuildError::ParserError(ref err) => err.fmt(f),
+            BuildError::ParserError(ref err, _) => err.fmt(f),
+            BuildError::MultipleRoots { ref name, .. } => {
+                write!(f, "Element <{}> after the root element.", name)
+            }
+            BuildError::TextOutsideRoot { .. } => write!(f, "Text outside of the root element."),
+            BuildError::UnclosedElement { ref name, .. } => {
+                write!(f, "Unexpected end of document, <{}> is not closed.", name)
+            }
+            BuildError::UnexpectedEndTag { ref name, .. } => write!(f, "Unexpected end tag </{}>.", name),
+            BuildError::MismatchedTag { ref expected, ref found, .. } => {
+                write!(f, "Mismatched end tag </{}>, expected </{}>.", found, expected)
+            }
+            BuildError::LimitExceeded { limit, max, .. } => {
+                write!(f, "Limit exceeded: {} (maximum {}).", limit, max)
+            }
+            BuildError::Encoding { ref message, .. } => write!(f, "Encoding error: {}", message),
+            BuildError::Io(ref err) => err.fmt(f),
+            BuildError::File(ref path, ref err) => return write!(f, "{}: {}", path.display(), err),
+        });
+        let path = self.path();
+        if !path.is_empty() {
+            try!(write!(f, " (in /{})", path.join("/")));
         }
+        Ok(())
+    }
+
+}
+
+/// A `BuildError` along with what was built before it occurred.
+#[derive(Debug)]
+pub struct PartialBuildError {
+    /// the error which stopped the build
+    pub error: BuildError,
+    /// the document built so far, `None` if the root element had not started
+    pub document: Option<Document>,
+    /// the qualified names of the elements open at the time, from the root down
+    pub open: Vec<String>,
+}
+
+impl fmt::Display for PartialBuildError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
     }
 
 }
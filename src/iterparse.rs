@@ -0,0 +1,211 @@
+use std::io::Read;
+
+use builder::{qualified_name, TreeBuilder};
+use dom::{Position, RcElement, ToQName};
+use error::BuildError;
+use options::BuildOptions;
+
+use xml::common::HasPosition;
+use xml::name::OwnedName;
+use xml::namespace::Namespace;
+use xml::reader::EventReader;
+use xml::reader::events::XmlEvent;
+
+/// `iterparse` creates an iterator over the elements of a document which
+/// match `predicate`, each yielded as soon as its end tag is read.
+///
+/// The predicate is given the names of the open elements, from the root
+/// down to the candidate element. Only the matching elements are built,
+/// the rest of the document is read and discarded, so memory stays
+/// proportional to the largest matching element. Elements within a
+/// matching element are part of it and are not tested.
+///
+/// The iteration stops after the first error.
+pub fn iterparse<B, P>(reader: EventReader<B>, predicate: P) -> IterParse<B, P>
+    where B: Read, P: FnMut(&[OwnedName]) -> bool
+{
+    iterparse_with_options(reader, predicate, BuildOptions::new())
+}
+
+/// `iterparse_with_options` creates an iterator like `iterparse`, building
+/// the elements according to the given `BuildOptions`.
+pub fn iterparse_with_options<B, P>(reader: EventReader<B>, predicate: P, options: BuildOptions) -> IterParse<B, P>
+    where B: Read, P: FnMut(&[OwnedName]) -> bool
+{
+    IterParse {
+        reader: reader,
+        predicate: predicate,
+        options: options,
+        path: Vec::new(),
+        builder: None,
+        depth: 0,
+        done: false,
+    }
+}
+
+/// Check if the names at the end of `path` are those of `pattern`, a list
+/// of names separated by `/`, such as `records/record`. With a leading `/`,
+/// the pattern must match the whole path.
+///
+/// The names are resolved like in `Element::find`, see `ToQName`, but
+/// without namespace bindings: a name without a prefix is in no namespace,
+/// names in a namespace are written in Clark notation, `{uri}record`.
+pub fn path_matches(path: &[OwnedName], pattern: &str) -> bool {
+    let (anchored, pattern) = if pattern.starts_with('/') {
+        (true, &pattern[1..])
+    } else {
+        (false, pattern)
+    };
+    let names = split_pattern(pattern);
+    if anchored && names.len() != path.len() {
+        return false;
+    }
+    path_ends_with(path, &names)
+}
+
+/// Check if the names at the end of `path` are the given names,
+/// matched like in `path_matches`.
+pub fn path_ends_with<N: ToQName>(path: &[OwnedName], names: &[N]) -> bool {
+    if names.len() > path.len() {
+        return false;
+    }
+    let tail = &path[path.len() - names.len()..];
+    tail.iter().zip(names.iter()).all(|(name, test)| {
+        match test.to_qname(&Namespace::empty()) {
+            Some(qname) => qname.matches(name),
+            None => false,
+        }
+    })
+}
+
+/// Split a pattern at the `/` which are not in a namespace URI.
+fn split_pattern(pattern: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut start = 0;
+    let mut in_uri = false;
+    for (i, c) in pattern.char_indices() {
+        match c {
+            '{' => in_uri = true,
+            '}' => in_uri = false,
+            '/' if !in_uri => {
+                names.push(&pattern[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    names.push(&pattern[start..]);
+    names
+}
+
+/// An iterator over the matching elements of a document. See `iterparse`.
+pub struct IterParse<B: Read, P> {
+    reader: EventReader<B>,
+    predicate: P,
+    options: BuildOptions,
+    // the names of the open elements, from the root down
+    path: Vec<OwnedName>,
+    // the matching element being built, its depth in the path
+    builder: Option<TreeBuilder>,
+    depth: usize,
+    done: bool,
+}
+
+impl<B: Read, P: FnMut(&[OwnedName]) -> bool> IterParse<B, P> {
+
+    fn error_path(&self) -> Vec<String> {
+        self.path.iter().map(qualified_name).collect()
+    }
+
+}
+
+impl<B: Read, P: FnMut(&[OwnedName]) -> bool> Iterator for IterParse<B, P> {
+
+    type Item = Result<RcElement, BuildError>;
+
+    fn next(&mut self) -> Option<Result<RcElement, BuildError>> {
+        while !self.done {
+            let pos = Position::new(self.reader.row() + 1, self.reader.col() + 1);
+            let event = self.reader.next();
+            let event = match event {
+                XmlEvent::StartElement { name, attributes, namespace } => {
+                    self.path.push(name.clone());
+                    if self.builder.is_none() && (self.predicate)(&self.path) {
+                        self.builder = Some(TreeBuilder::with_options(self.options.clone()));
+                        self.depth = self.path.len();
+                    }
+                    XmlEvent::StartElement { name: name, attributes: attributes, namespace: namespace }
+                }
+                XmlEvent::EndElement { name } => {
+                    self.path.pop();
+                    XmlEvent::EndElement { name: name }
+                }
+                XmlEvent::EndDocument => {
+                    self.done = true;
+                    continue;
+                }
+                XmlEvent::Error(err) => {
+                    self.done = true;
+                    return Some(Err(BuildError::ParserError(err, self.error_path())));
+                }
+                event => event,
+            };
+
+            let finished = match self.builder {
+                None => continue,
+                Some(ref mut builder) => {
+                    if let Err(err) = builder.feed_with_position(event, pos) {
+                        self.done = true;
+                        return Some(Err(err));
+                    }
+                    self.path.len() < self.depth
+                }
+            };
+            if finished {
+                let builder = self.builder.take().unwrap();
+                return Some(builder.finish().map(|doc| doc.root));
+            }
+        }
+        None
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use dom::QName;
+    use super::{iterparse, path_ends_with, path_matches};
+
+    use xml::EventReader;
+
+    #[test]
+    fn test_iterparse() {
+        let xml = "<dump><records><record>a</record><record>b<record>c</record></record></records><record>d</record></dump>";
+        let records: Vec<String> = iterparse(EventReader::new(xml.as_bytes()), |path| path_matches(path, "records/record"))
+            .map(|elem| elem.unwrap().borrow().to_string())
+            .collect();
+        assert_eq!(records, ["<record>a</record>", "<record>b<record>c</record></record>"]);
+
+        let count = iterparse(EventReader::new(xml.as_bytes()), |path| path_matches(path, "/dump/record")).count();
+        assert_eq!(count, 1);
+
+        let xml = "<dump><record>a</record><record>b</dump>";
+        let results: Vec<_> = iterparse(EventReader::new(xml.as_bytes()), |path| path_matches(path, "record")).collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        match results[1] {
+            Err(ref err) => assert_eq!(err.path(), ["dump", "record"]),
+            Ok(_) => panic!("Expected an error."),
+        }
+
+        let xml = "<dump xmlns:a=\"http://a/ns\"><a:record>a</a:record><record>b</record></dump>";
+        let count = iterparse(EventReader::new(xml.as_bytes()), |path| path_matches(path, "dump/{http://a/ns}record")).count();
+        assert_eq!(count, 1);
+        let count = iterparse(EventReader::new(xml.as_bytes()), |path| path_matches(path, "record")).count();
+        assert_eq!(count, 1);
+        let names = [QName::new(None, "dump"), QName::new(None, "record")];
+        let count = iterparse(EventReader::new(xml.as_bytes()), |path| path_ends_with(path, &names)).count();
+        assert_eq!(count, 1);
+    }
+
+}
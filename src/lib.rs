@@ -2,14 +2,23 @@
 
 extern crate xml;
 
-pub use builder::build;
+pub use builder::{build, build_fragment, build_fragment_with_options, build_from_events, build_from_path, build_from_reader, build_from_reader_with_options, build_from_str, build_lenient, build_partial, build_with_options, default_config};
+pub use builder::TreeBuilder;
 pub use dom::{
-    Document,
+    Comment, CommentIterator, RcComment,
+    Doctype, Document,
     Element, ElementIterator, RcElement, WeakElement,
+    EventIterator,
     Text, TextIterator,
-    Node, RcNode,
+    Node, RcNode, Position,
+    QName, ToQName,
+    ProcessingInstruction, ProcessingInstructionIterator, RcProcessingInstruction,
+    WriterConfig, XmlDeclaration,
 };
-pub use error::BuildError;
+pub use error::{BuildError, PartialBuildError};
+pub use iterparse::{iterparse, iterparse_with_options, path_ends_with, path_matches, IterParse};
+pub use options::{BuildOptions, WhitespaceMode};
+pub use push::PushBuilder;
 
 pub use xml::EventReader;
 pub use xml::attribute::OwnedAttribute;
@@ -23,3 +32,6 @@ pub use xml::reader::events::XmlEvent;
 mod builder;
 mod dom;
 mod error;
+mod iterparse;
+mod options;
+mod push;
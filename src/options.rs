@@ -0,0 +1,117 @@
+/// Options controlling how the builder creates a `Document`.
+///
+/// They are independent of the `ParserConfig` of the `EventReader`:
+/// whitespace is handled the same way whether the parser reports it as
+/// whitespace or as characters.
+#[derive(Clone, Debug)]
+pub struct BuildOptions {
+    /// How text nodes made of whitespace, such as indentation, are handled.
+    pub whitespace: WhitespaceMode,
+    /// Preserve text verbatim inside elements with `xml:space="preserve"`,
+    /// regardless of `whitespace`, until an `xml:space="default"` is found.
+    pub respect_xml_space: bool,
+    /// Merge adjacent text nodes, such as CDATA sections and the text
+    /// around them, into a single text node.
+    pub coalesce_text: bool,
+    /// Maximum nesting depth of elements.
+    pub max_depth: Option<usize>,
+    /// Maximum number of nodes in the document, once whitespace is
+    /// handled and text coalesced.
+    pub max_nodes: Option<usize>,
+    /// Maximum number of attributes of an element.
+    pub max_attributes: Option<usize>,
+    /// Maximum number of namespace bindings in scope of an element, as
+    /// reported by the parser. Each element keeps a copy of them.
+    pub max_namespaces: Option<usize>,
+    /// Maximum length of a text node, an attribute value, a comment or the
+    /// data of a processing instruction, in bytes.
+    pub max_text_length: Option<usize>,
+    /// Maximum length of a prefix or a local name, in bytes.
+    pub max_name_length: Option<usize>,
+    /// Maximum size of the source document, in bytes. Only the entry points
+    /// which read the bytes check it: `build_from_reader_with_options`,
+    /// `build_fragment_with_options` and `PushBuilder`.
+    pub max_total_bytes: Option<usize>,
+}
+
+/// How the builder handles whitespace in text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhitespaceMode {
+    /// Keep all text verbatim, including indentation.
+    Preserve,
+    /// Drop text nodes which only contain whitespace.
+    Drop,
+    /// Trim whitespace around text and drop text nodes which end up empty.
+    Trim,
+}
+
+impl BuildOptions {
+
+    /// Create options which drop whitespace only text nodes
+    /// and respect `xml:space`, without any limits.
+    pub fn new() -> BuildOptions {
+        BuildOptions {
+            whitespace: WhitespaceMode::Drop,
+            respect_xml_space: true,
+            coalesce_text: false,
+            max_depth: None,
+            max_nodes: None,
+            max_attributes: None,
+            max_namespaces: None,
+            max_text_length: None,
+            max_name_length: None,
+            max_total_bytes: None,
+        }
+    }
+
+    pub fn whitespace(mut self, value: WhitespaceMode) -> BuildOptions {
+        self.whitespace = value;
+        self
+    }
+
+    pub fn respect_xml_space(mut self, value: bool) -> BuildOptions {
+        self.respect_xml_space = value;
+        self
+    }
+
+    pub fn coalesce_text(mut self, value: bool) -> BuildOptions {
+        self.coalesce_text = value;
+        self
+    }
+
+    pub fn max_depth(mut self, value: usize) -> BuildOptions {
+        self.max_depth = Some(value);
+        self
+    }
+
+    pub fn max_nodes(mut self, value: usize) -> BuildOptions {
+        self.max_nodes = Some(value);
+        self
+    }
+
+    pub fn max_attributes(mut self, value: usize) -> BuildOptions {
+        self.max_attributes = Some(value);
+        self
+    }
+
+    pub fn max_namespaces(mut self, value: usize) -> BuildOptions {
+        self.max_namespaces = Some(value);
+        self
+    }
+
+    pub fn max_text_length(mut self, value: usize) -> BuildOptions {
+        self.max_text_length = Some(value);
+        self
+    }
+
+    pub fn max_name_length(mut self, value: usize) -> BuildOptions {
+        self.max_name_length = Some(value);
+        self
+    }
+
+    pub fn max_total_bytes(mut self, value: usize) -> BuildOptions {
+        self.max_total_bytes = Some(value);
+        self
+    }
+
+}
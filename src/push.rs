@@ -0,0 +1,259 @@
+use std::io::{self, Read};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use builder::{default_config, total_bytes_exceeded, PrologScanner, TreeBuilder};
+use dom::{Document, Position};
+use error::BuildError;
+use options::BuildOptions;
+
+use xml::common::HasPosition;
+use xml::reader::EventReader;
+use xml::reader::config::ParserConfig;
+use xml::reader::events::XmlEvent;
+
+/// `PushBuilder` creates a `Document` from bytes pushed to it as they
+/// arrive, such as chunks read from a socket, instead of pulling them
+/// from a blocking `Read`.
+///
+/// The parser only pulls bytes from a `Read`, and cannot resume once a
+/// read returned fewer bytes than the document has: it reports the end
+/// of the input as an error and stops. Parsing everything again on each
+/// `feed` would take quadratic time. So each builder spawns a thread for
+/// the parser, which blocks in `read` until more bytes are fed, and lives
+/// until `finish` or until the builder is dropped, which ends its input.
+/// The bytes are copied to it and its events sent back through
+/// channels. Each call to `feed` returns once the parser needs more bytes,
+/// with the events found so far added to the document. Like `build_from_reader`,
+/// the builder watches the bytes of the prolog for what the parser does
+/// not report: the document type declaration and whether there is an XML
+/// declaration.
+pub struct PushBuilder {
+    builder: TreeBuilder,
+    scanner: PrologScanner,
+    options: BuildOptions,
+    // bytes fed so far, counted against `max_total_bytes`
+    bytes: usize,
+    // None once the end of the input was signaled
+    data: Option<Sender<Vec<u8>>>,
+    messages: Receiver<Message>,
+    parser: Option<JoinHandle<()>>,
+    error: Option<BuildError>,
+    ended: bool,
+}
+
+enum Message {
+    Event(XmlEvent, Position),
+    // the parser read all the bytes fed so far
+    NeedMore,
+}
+
+impl PushBuilder {
+
+    /// Create a builder with the `ParserConfig` of `default_config()`,
+    /// which keeps comments, and the default `BuildOptions`.
+    pub fn new() -> PushBuilder {
+        PushBuilder::with_config(default_config(), BuildOptions::new())
+    }
+
+    pub fn with_config(config: ParserConfig, options: BuildOptions) -> PushBuilder {
+        let (data_tx, data_rx) = channel();
+        let (messages_tx, messages_rx) = channel();
+        let parser = thread::spawn(move || {
+            let source = ChannelReader {
+                data: data_rx,
+                messages: messages_tx.clone(),
+                buf: Vec::new(),
+                pos: 0,
+            };
+            let mut reader = EventReader::new_with_config(source, config);
+            loop {
+                let pos = Position::new(reader.row() + 1, reader.col() + 1);
+                let event = reader.next();
+                let end = match event {
+                    XmlEvent::EndDocument | XmlEvent::Error(_) => true,
+                    _ => false,
+                };
+                if messages_tx.send(Message::Event(event, pos)).is_err() || end {
+                    break;
+                }
+            }
+        });
+
+        let mut push = PushBuilder {
+            builder: TreeBuilder::with_options(options.clone()),
+            scanner: PrologScanner::new(),
+            options: options,
+            bytes: 0,
+            data: Some(data_tx),
+            messages: messages_rx,
+            parser: Some(parser),
+            error: None,
+            ended: false,
+        };
+        // the parser asks for bytes before finding any event
+        push.receive();
+        push
+    }
+
+    /// Push the next bytes of the source document.
+    /// Nothing is done once the builder has failed.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        // an empty read would be the end of the input
+        if bytes.is_empty() || self.is_failed() || self.ended {
+            return;
+        }
+        self.bytes += bytes.len();
+        if let Some(max) = self.options.max_total_bytes {
+            if self.bytes > max {
+                self.error = Some(total_bytes_exceeded(&self.options));
+                return;
+            }
+        }
+        self.scanner.scan(bytes);
+        let sent = match self.data {
+            Some(ref data) => data.send(bytes.to_vec()).is_ok(),
+            None => false,
+        };
+        if sent {
+            self.receive();
+        }
+    }
+
+    /// Check if the source document was found to be invalid.
+    pub fn is_failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Get the document built so far, if the root element has started.
+    /// It shares its nodes with the builder, they keep changing as more
+    /// bytes are fed. The XML declaration and the document type declaration
+    /// are only those found in the bytes once all of the prolog was fed.
+    pub fn document(&self) -> Option<Document> {
+        self.builder.partial().map(|mut doc| {
+            if self.scanner.is_done() {
+                self.scanner.prolog.apply(&mut doc);
+            }
+            doc
+        })
+    }
+
+    /// Signal the end of the source document and get the document.
+    pub fn finish(mut self) -> Result<Document, BuildError> {
+        self.data = None;
+        self.scanner.scan(&[]);
+        if !self.is_failed() {
+            self.receive();
+        }
+        if let Some(parser) = self.parser.take() {
+            // the events stopped early, whatever was built is not the document
+            if parser.join().is_err() && self.error.is_none() {
+                let err = io::Error::new(io::ErrorKind::Other, "The parser thread panicked.");
+                self.error = Some(BuildError::Io(err));
+            }
+        }
+        match self.error {
+            Some(err) => Err(err),
+            None => {
+                let mut doc = try!(self.builder.finish());
+                self.scanner.prolog.apply(&mut doc);
+                Ok(doc)
+            }
+        }
+    }
+
+    /// Add the events of the parser to the document, until it needs more
+    /// bytes or stops.
+    fn receive(&mut self) {
+        while let Ok(message) = self.messages.recv() {
+            match message {
+                Message::NeedMore => break,
+                Message::Event(event, pos) => {
+                    let end = match event {
+                        XmlEvent::EndDocument | XmlEvent::Error(_) => true,
+                        _ => false,
+                    };
+                    if let Err(err) = self.builder.feed_with_position(event, pos) {
+                        self.error = Some(err);
+                        break;
+                    }
+                    if end {
+                        self.ended = true;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+}
+
+/// Reads the bytes sent through a channel, asking for more when it runs out.
+/// The input ends when the sending side of the channel is dropped.
+struct ChannelReader {
+    data: Receiver<Vec<u8>>,
+    messages: Sender<Message>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() {
+            let _ = self.messages.send(Message::NeedMore);
+            match self.data.recv() {
+                Ok(buf) => {
+                    self.buf = buf;
+                    self.pos = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = try!((&self.buf[self.pos..]).read(out));
+        self.pos += n;
+        Ok(n)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use builder::build_from_str;
+    use error::BuildError;
+    use super::PushBuilder;
+
+    #[test]
+    fn test_push_builder() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?><root><item>aa</item><item>bb</item></root>";
+        let mut push = PushBuilder::new();
+        assert!(push.document().is_none());
+        let (head, tail) = xml.as_bytes().split_at(54);
+        push.feed(head);
+        {
+            let partial = push.document().unwrap();
+            assert_eq!(partial.root.borrow().find("item").len(), 1);
+            assert!(partial.has_declaration());
+        }
+        for byte in tail.iter() {
+            push.feed(&[*byte]);
+        }
+        assert!(!push.is_failed());
+        let doc = push.finish().unwrap();
+        assert_eq!(doc.to_string(), build_from_str(xml, None).unwrap().to_string());
+
+        let mut push = PushBuilder::new();
+        push.feed(b"<root><item></root>");
+        push.feed(b"<more/>");
+        assert!(push.is_failed());
+        assert!(push.finish().is_err());
+
+        let mut push = PushBuilder::new();
+        push.feed(b"<root>");
+        match push.finish() {
+            Err(BuildError::ParserError(..)) | Err(BuildError::UnclosedElement { .. }) => {}
+            _ => panic!("Expected the truncated document to be rejected."),
+        }
+    }
+
+}